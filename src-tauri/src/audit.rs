@@ -0,0 +1,145 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  fs::OpenOptions,
+  io::Write,
+  path::Path,
+  sync::Mutex,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{error::AgentError, registry::AgentInfo, shell_word, AgentSpec};
+
+/// Substrings that mark an environment variable as holding a credential.
+const SENSITIVE_NAMES: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL", "AUTH"];
+
+/// What replaces the value of a sensitive variable in the log.
+const REDACTED: &str = "[redacted]";
+
+/// One line of the audit log: a launch that was attempted and how it went.
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+  /// Milliseconds since the Unix epoch.
+  timestamp: u64,
+  command: String,
+  working_directory: Option<&'a str>,
+  env: BTreeMap<&'a str, &'a str>,
+  /// The terminal the agent was opened in, or the one asked for if it failed.
+  terminal: Option<&'a str>,
+  ssh_host: Option<&'a str>,
+  /// `launched` or `failed`.
+  outcome: &'static str,
+  id: Option<&'a str>,
+  pid: Option<u32>,
+  error: Option<String>,
+}
+
+/// Whether the environment variable `name` looks like it holds a credential.
+pub fn is_sensitive(name: &str) -> bool {
+  let upper = name.to_ascii_uppercase();
+  SENSITIVE_NAMES.iter().any(|pattern| upper.contains(pattern))
+}
+
+/// Returns `env` in name order, with the values of variables that look like
+/// credentials replaced.
+fn redact_env(env: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+  env
+    .iter()
+    .map(|(name, value)| (name.as_str(), if is_sensitive(name) { REDACTED } else { value.as_str() }))
+    .collect()
+}
+
+fn entry<'a>(
+  spec: &'a AgentSpec,
+  result: &'a Result<AgentInfo, AgentError>,
+  env: &'a HashMap<String, String>,
+) -> AuditEntry<'a> {
+  let timestamp = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or(0);
+  let (outcome, info, error) = match result {
+    Ok(info) => ("launched", Some(info), None),
+    Err(e) => ("failed", None, Some(e.to_string())),
+  };
+  AuditEntry {
+    timestamp,
+    command: spec.command_line(shell_word),
+    working_directory: spec.working_directory.as_deref(),
+    env: redact_env(env),
+    terminal: info.map(|info| info.terminal.as_str()).or(spec.terminal.as_deref()),
+    ssh_host: spec.ssh_host.as_deref(),
+    outcome,
+    id: info.map(|info| info.id.as_str()),
+    pid: info.map(|info| info.pid),
+    error,
+  }
+}
+
+/// Appends a JSON line describing the launch of `spec` to the log at `path`.
+///
+/// A failure to write the audit log is logged as an error but doesn't fail the
+/// launch, which has already happened by the time it is recorded.
+pub fn record(path: &Path, spec: &AgentSpec, result: &Result<AgentInfo, AgentError>) {
+  // Parallel launches each write a whole line at a time
+  static WRITER: Mutex<()> = Mutex::new(());
+
+  let env = spec.env();
+  let Ok(line) = serde_json::to_string(&entry(spec, result, &env)) else {
+    return;
+  };
+  let _guard = WRITER.lock().unwrap();
+  let written = path
+    .parent()
+    .map_or(Ok(()), std::fs::create_dir_all)
+    .and_then(|()| OpenOptions::new().create(true).append(true).open(path))
+    .and_then(|mut file| writeln!(file, "{}", line));
+  if let Err(e) = written {
+    log::error!("Could not write audit log {}: {}", path.display(), e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn redacts_credentials_but_keeps_other_values() {
+    let env = HashMap::from([
+      ("ANTHROPIC_API_KEY".to_string(), "sk-123".to_string()),
+      ("github_token".to_string(), "ghp".to_string()),
+      ("MODEL".to_string(), "big".to_string()),
+    ]);
+    let redacted = redact_env(&env);
+    assert_eq!(
+      redacted.into_iter().collect::<Vec<_>>(),
+      [("ANTHROPIC_API_KEY", REDACTED), ("MODEL", "big"), ("github_token", REDACTED)]
+    );
+  }
+
+  #[test]
+  fn record_appends_one_json_line_per_launch() {
+    let path = std::env::temp_dir()
+      .join(format!("agentic-sync-audit-{}", std::process::id()))
+      .join("audit.log");
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      env: Some(HashMap::from([("SECRET".to_string(), "hunter2".to_string())])),
+      terminal: Some("kitty".to_string()),
+      ..AgentSpec::default()
+    };
+    record(&path, &spec, &Err(AgentError::NoTerminalFound(Vec::new())));
+    record(&path, &spec, &Err(AgentError::EmptyCommand));
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["command"], "claude");
+    assert_eq!(lines[0]["outcome"], "failed");
+    assert_eq!(lines[0]["terminal"], "kitty");
+    assert_eq!(lines[0]["env"]["SECRET"], REDACTED);
+    assert!(!log.contains("hunter2"));
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+  }
+}
@@ -0,0 +1,176 @@
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::AgentSpec;
+
+/// Per-user launch defaults, read from `~/.config/agentic-sync/terminal.toml`.
+///
+/// ```toml
+/// startup_delay_ms = 1000
+/// shell = "zsh"
+/// keep_agents_on_exit = false
+/// max_concurrent_agents = 8
+/// audit_log = "/var/log/agentic-sync/audit.log"
+/// max_retries = 2
+/// retry_delay_ms = 250
+/// dedupe_window_ms = 0
+/// max_command_length = 200000
+///
+/// [linux]
+/// terminal = "kitty"
+/// terminal_preference = ["ghostty", "alacritty"]
+///
+/// [macos]
+/// terminal = "iterm"
+///
+/// [windows]
+/// shell = "pwsh"
+/// ```
+///
+/// Every key is optional; anything left out keeps the built-in behaviour.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+  pub macos: PlatformConfig,
+  pub windows: PlatformConfig,
+  pub linux: PlatformConfig,
+  pub startup_delay_ms: Option<u64>,
+  /// Shell left running in the window once the agent command finishes, on
+  /// macOS and Linux.
+  pub shell: Option<String>,
+  /// Leave agents running when the app quits instead of stopping them.
+  pub keep_agents_on_exit: bool,
+  /// How many agents may run at once; unlimited when unset.
+  pub max_concurrent_agents: Option<usize>,
+  /// File every launch is recorded in; `audit.log` in the app's data
+  /// directory when unset.
+  pub audit_log: Option<String>,
+  /// Defaults for the launch options of the same names.
+  pub max_retries: Option<u32>,
+  pub retry_delay_ms: Option<u64>,
+  pub dedupe_window_ms: Option<u64>,
+  pub max_command_length: Option<usize>,
+}
+
+/// Settings that only make sense on one platform.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlatformConfig {
+  pub terminal: Option<String>,
+  /// Terminals to try after `terminal`, in order; see the launch option.
+  pub terminal_preference: Option<Vec<String>>,
+  /// Overrides the top-level `shell`; on Windows, one of `cmd`, `powershell`
+  /// or `pwsh`.
+  pub shell: Option<String>,
+}
+
+impl TerminalConfig {
+  /// The section for the platform this build targets.
+  fn platform(&self) -> &PlatformConfig {
+    if cfg!(target_os = "macos") {
+      &self.macos
+    } else if cfg!(target_os = "windows") {
+      &self.windows
+    } else {
+      &self.linux
+    }
+  }
+
+  /// The preferred terminal for the platform this build targets.
+  pub fn terminal(&self) -> Option<&str> {
+    self.platform().terminal.as_deref()
+  }
+
+  /// The preferred shell for the platform this build targets. A POSIX shell
+  /// named at the top level means nothing to Windows, so it isn't used there.
+  pub fn shell(&self) -> Option<&str> {
+    let fallback = self.shell.as_deref().filter(|_| !cfg!(target_os = "windows"));
+    self.platform().shell.as_deref().or(fallback)
+  }
+
+  /// Returns `spec` with every option the caller left unset filled in from
+  /// the config.
+  pub fn apply(&self, spec: &AgentSpec) -> AgentSpec {
+    let mut spec = spec.clone();
+    spec.terminal = spec.terminal.or_else(|| self.terminal().map(str::to_string));
+    spec.terminal_preference = spec.terminal_preference.or_else(|| self.platform().terminal_preference.clone());
+    spec.startup_delay_ms = spec.startup_delay_ms.or(self.startup_delay_ms);
+    spec.shell = spec.shell.or_else(|| self.shell().map(str::to_string));
+    spec.max_concurrent_agents = spec.max_concurrent_agents.or(self.max_concurrent_agents);
+    spec.max_retries = spec.max_retries.or(self.max_retries);
+    spec.retry_delay_ms = spec.retry_delay_ms.or(self.retry_delay_ms);
+    spec.dedupe_window_ms = spec.dedupe_window_ms.or(self.dedupe_window_ms);
+    spec.max_command_length = spec.max_command_length.or(self.max_command_length);
+    spec
+  }
+}
+
+/// Where the config file lives, if there is a home directory to put it in.
+pub fn config_path() -> Option<PathBuf> {
+  tauri::api::path::home_dir().map(|home| home.join(".config/agentic-sync/terminal.toml"))
+}
+
+/// Loads the config file, falling back to the built-in defaults when it is
+/// missing. A file that can't be parsed is reported and otherwise ignored so
+/// a typo doesn't stop the app from starting.
+pub fn load_terminal_config() -> TerminalConfig {
+  let Some(path) = config_path() else {
+    return TerminalConfig::default();
+  };
+  match fs::read_to_string(&path) {
+    Ok(contents) => parse_terminal_config(&contents).unwrap_or_else(|e| {
+      log::warn!("Ignoring invalid terminal config {}: {}", path.display(), e);
+      TerminalConfig::default()
+    }),
+    Err(_) => TerminalConfig::default(),
+  }
+}
+
+fn parse_terminal_config(contents: &str) -> Result<TerminalConfig, toml::de::Error> {
+  toml::from_str(contents)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_partial_config() {
+    let config = parse_terminal_config(
+      r#"
+        startup_delay_ms = 500
+
+        [linux]
+        terminal = "kitty"
+      "#,
+    )
+    .unwrap();
+    assert_eq!(config.startup_delay_ms, Some(500));
+    assert_eq!(config.linux.terminal.as_deref(), Some("kitty"));
+    assert_eq!(config.macos, PlatformConfig::default());
+    assert_eq!(config.shell, None);
+    assert!(!config.keep_agents_on_exit);
+    assert_eq!(parse_terminal_config("").unwrap(), TerminalConfig::default());
+  }
+
+  #[test]
+  fn rejects_wrongly_typed_values() {
+    assert!(parse_terminal_config("startup_delay_ms = \"soon\"").is_err());
+  }
+
+  #[test]
+  fn apply_only_fills_unset_options() {
+    let config = TerminalConfig {
+      startup_delay_ms: Some(500),
+      shell: Some("zsh".to_string()),
+      ..TerminalConfig::default()
+    };
+    let spec = AgentSpec { command: "claude".to_string(), startup_delay_ms: Some(0), ..AgentSpec::default() };
+
+    let applied = config.apply(&spec);
+    assert_eq!(applied.startup_delay_ms, Some(0));
+    assert_eq!(applied.shell.as_deref(), Some("zsh"));
+    assert_eq!(applied.terminal, None);
+  }
+}
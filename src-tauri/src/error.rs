@@ -0,0 +1,245 @@
+use std::fmt;
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+/// Errors returned by the agent commands.
+///
+/// Serialized as `{ "kind": "<Variant>", "message": "<human readable>" }` so
+/// the frontend can branch on `kind` and still show `message` as-is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+// Some variants are only produced by the launcher for a particular platform
+#[allow(dead_code)]
+pub enum AgentError {
+  /// The command was empty or only whitespace.
+  EmptyCommand,
+  /// The command or one of its arguments has a line break in it.
+  MultilineCommand,
+  /// The named launch option, which ends up in the same shell line as the
+  /// command, has a line break in it.
+  MultilineOption(String),
+  /// None of the terminal emulators we know how to drive are installed. Lists
+  /// the ones looked for, where there was a choice.
+  NoTerminalFound(Vec<String>),
+  /// Every terminal tried failed, as `(terminal, reason)` in the order tried.
+  AllTerminalsFailed(Vec<(String, String)>),
+  /// The command line is `length` bytes long, more than the `limit` allowed.
+  CommandTooLong { length: usize, limit: usize },
+  /// The terminal (or its launcher) could not be spawned.
+  SpawnFailed(String),
+  /// The launcher ran but reported that it couldn't open the terminal.
+  LaunchFailed(String),
+  /// macOS won't let the app automate the named terminal, and opening it
+  /// without automation failed too.
+  PermissionDenied(String),
+  /// The requested working directory doesn't exist or isn't a directory.
+  InvalidWorkingDir(String),
+  /// The caller asked for a terminal this platform doesn't support.
+  UnsupportedTerminal(String),
+  /// The caller asked for a shell this platform doesn't support.
+  UnsupportedShell(String),
+  /// The caller asked for a supported terminal that isn't installed.
+  TerminalNotInstalled(String),
+  /// WSL was requested but can't be used here.
+  WslUnavailable(String),
+  /// An environment variable name isn't a valid shell identifier.
+  InvalidEnvVar(String),
+  /// The environment to activate can't be used, for the given reason.
+  InvalidEnvironment(String),
+  /// An extra terminal argument repeats the command.
+  InvalidTerminalArgs(String),
+  /// The SSH host is empty or can't be a host name.
+  InvalidSshHost(String),
+  /// The SSH user is empty or can't be a user name.
+  InvalidSshUser(String),
+  /// The launcher didn't finish within the given number of milliseconds.
+  Timeout(u64),
+  /// The program the command would run isn't installed.
+  CommandNotFound(String),
+  /// The script to launch doesn't exist or isn't a file.
+  ScriptNotFound(String),
+  /// The script to launch exists but can't be run: it has no execute
+  /// permission, or on Windows isn't a kind of file a shell runs.
+  ScriptNotExecutable(String),
+  /// The given number of agents are already running.
+  AgentLimitReached(usize),
+  /// The agent is past its startup delay, so its launch can't be cancelled.
+  AgentAlreadyStarted(String),
+  /// A caller-chosen agent id is empty or has surrounding whitespace.
+  InvalidAgentId(String),
+  /// Another running agent already has the caller-chosen id.
+  DuplicateAgentId(String),
+  /// The same command was just launched in the same directory, as the agent
+  /// with the given id.
+  DuplicateLaunch(String),
+  /// The agent was saved without the values of the named environment
+  /// variables, which restarting it needs given again.
+  MissingEnv(Vec<String>),
+  /// No agent with the given id has been launched this session.
+  AgentNotFound(String),
+  /// A group id is empty or has surrounding whitespace.
+  InvalidGroupId(String),
+  /// No registered agent belongs to the given group.
+  GroupNotFound(String),
+  /// There is no running process with the given PID.
+  ProcessNotFound(u32),
+  /// No window belonging to the process could be found to bring forward.
+  WindowNotFound(u32),
+  /// The agent's terminal can't be sent commands, or its window can't be
+  /// told apart to bring forward.
+  UnscriptableTerminal(String),
+  /// The terminal didn't take the command sent to it.
+  SendFailed(String),
+  /// Reading, writing or resizing an agent's pseudo-terminal failed.
+  PtyFailed(String),
+  /// The log file for the agent's output couldn't be set up.
+  LogFailed(String),
+  /// The process exists but couldn't be terminated.
+  KillFailed { pid: u32, reason: String },
+}
+
+impl AgentError {
+  fn kind(&self) -> &'static str {
+    match self {
+      AgentError::EmptyCommand => "EmptyCommand",
+      AgentError::MultilineCommand => "MultilineCommand",
+      AgentError::MultilineOption(_) => "MultilineOption",
+      AgentError::NoTerminalFound(_) => "NoTerminalFound",
+      AgentError::AllTerminalsFailed(_) => "AllTerminalsFailed",
+      AgentError::CommandTooLong { .. } => "CommandTooLong",
+      AgentError::SpawnFailed(_) => "SpawnFailed",
+      AgentError::LaunchFailed(_) => "LaunchFailed",
+      AgentError::PermissionDenied(_) => "PermissionDenied",
+      AgentError::InvalidWorkingDir(_) => "InvalidWorkingDir",
+      AgentError::UnsupportedTerminal(_) => "UnsupportedTerminal",
+      AgentError::UnsupportedShell(_) => "UnsupportedShell",
+      AgentError::TerminalNotInstalled(_) => "TerminalNotInstalled",
+      AgentError::WslUnavailable(_) => "WslUnavailable",
+      AgentError::InvalidEnvVar(_) => "InvalidEnvVar",
+      AgentError::InvalidEnvironment(_) => "InvalidEnvironment",
+      AgentError::InvalidTerminalArgs(_) => "InvalidTerminalArgs",
+      AgentError::InvalidSshHost(_) => "InvalidSshHost",
+      AgentError::InvalidSshUser(_) => "InvalidSshUser",
+      AgentError::Timeout(_) => "Timeout",
+      AgentError::CommandNotFound(_) => "CommandNotFound",
+      AgentError::ScriptNotFound(_) => "ScriptNotFound",
+      AgentError::ScriptNotExecutable(_) => "ScriptNotExecutable",
+      AgentError::AgentLimitReached(_) => "AgentLimitReached",
+      AgentError::AgentAlreadyStarted(_) => "AgentAlreadyStarted",
+      AgentError::InvalidAgentId(_) => "InvalidAgentId",
+      AgentError::DuplicateAgentId(_) => "DuplicateAgentId",
+      AgentError::DuplicateLaunch(_) => "DuplicateLaunch",
+      AgentError::MissingEnv(_) => "MissingEnv",
+      AgentError::AgentNotFound(_) => "AgentNotFound",
+      AgentError::InvalidGroupId(_) => "InvalidGroupId",
+      AgentError::GroupNotFound(_) => "GroupNotFound",
+      AgentError::ProcessNotFound(_) => "ProcessNotFound",
+      AgentError::WindowNotFound(_) => "WindowNotFound",
+      AgentError::UnscriptableTerminal(_) => "UnscriptableTerminal",
+      AgentError::SendFailed(_) => "SendFailed",
+      AgentError::PtyFailed(_) => "PtyFailed",
+      AgentError::LogFailed(_) => "LogFailed",
+      AgentError::KillFailed { .. } => "KillFailed",
+    }
+  }
+}
+
+impl fmt::Display for AgentError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AgentError::EmptyCommand => write!(f, "Command is empty"),
+      AgentError::MultilineCommand => write!(f, "Command must be a single line"),
+      AgentError::MultilineOption(option) => write!(f, "The {} option must be a single line", option),
+      AgentError::NoTerminalFound(looked_for) if looked_for.is_empty() => {
+        write!(f, "No supported terminal emulator is installed")
+      }
+      AgentError::NoTerminalFound(looked_for) => write!(
+        f,
+        "No supported terminal emulator is installed (looked for {}). \
+         Install one of them, or set $TERMINAL to the terminal you use",
+        looked_for.join(", ")
+      ),
+      AgentError::AllTerminalsFailed(failures) => {
+        write!(f, "No terminal could be opened")?;
+        for (i, (terminal, reason)) in failures.iter().enumerate() {
+          write!(f, "{} {}: {}", if i == 0 { ":" } else { ";" }, terminal, reason)?;
+        }
+        Ok(())
+      }
+      AgentError::CommandTooLong { length, limit } => {
+        write!(f, "Command is {} bytes long, over the limit of {}", length, limit)
+      }
+      AgentError::SpawnFailed(reason) => write!(f, "Failed to launch terminal: {}", reason),
+      AgentError::LaunchFailed(reason) => write!(f, "Terminal failed to open: {}", reason),
+      AgentError::PermissionDenied(terminal) => write!(
+        f,
+        "Not allowed to control {}. Allow AgenticSync to control it in \
+         System Settings > Privacy & Security > Automation",
+        terminal
+      ),
+      AgentError::InvalidWorkingDir(dir) => write!(f, "Working directory does not exist: {}", dir),
+      AgentError::UnsupportedTerminal(name) => write!(f, "Unsupported terminal: {}", name),
+      AgentError::UnsupportedShell(name) => write!(f, "Unsupported shell: {}", name),
+      AgentError::TerminalNotInstalled(name) => write!(f, "Terminal is not installed: {}", name),
+      AgentError::WslUnavailable(reason) => write!(f, "WSL is unavailable: {}", reason),
+      AgentError::InvalidEnvVar(name) => write!(f, "Invalid environment variable name: {}", name),
+      AgentError::InvalidEnvironment(reason) => write!(f, "Can't activate the environment: {}", reason),
+      AgentError::InvalidTerminalArgs(arg) => {
+        write!(f, "Extra terminal arguments must not include the command: {:?}", arg)
+      }
+      AgentError::InvalidSshHost(host) => write!(f, "Invalid SSH host: {:?}", host),
+      AgentError::InvalidSshUser(user) => write!(f, "Invalid SSH user: {:?}", user),
+      AgentError::Timeout(ms) => write!(f, "Terminal did not start within {} ms", ms),
+      AgentError::CommandNotFound(program) => write!(f, "Command not found: {}", program),
+      AgentError::ScriptNotFound(path) => write!(f, "Script not found: {}", path),
+      AgentError::ScriptNotExecutable(path) => write!(f, "Script is not executable: {}", path),
+      AgentError::AgentLimitReached(limit) => write!(f, "Already running the limit of {} agents", limit),
+      AgentError::AgentAlreadyStarted(id) => write!(f, "Agent {} has already started", id),
+      AgentError::InvalidAgentId(id) => write!(f, "Invalid agent id: {:?}", id),
+      AgentError::DuplicateAgentId(id) => write!(f, "An agent with id {} is already running", id),
+      AgentError::DuplicateLaunch(id) => write!(f, "The same command was just launched as {}", id),
+      AgentError::MissingEnv(names) => {
+        write!(f, "Restarting needs the values of {} again, which aren't saved", names.join(", "))
+      }
+      AgentError::AgentNotFound(id) => write!(f, "No agent with id {}", id),
+      AgentError::InvalidGroupId(id) => write!(f, "Invalid group id: {:?}", id),
+      AgentError::GroupNotFound(id) => write!(f, "No group with id {}", id),
+      AgentError::ProcessNotFound(pid) => write!(f, "No process with PID {} is running", pid),
+      AgentError::WindowNotFound(pid) => write!(f, "Could not find a window for process {}", pid),
+      AgentError::UnscriptableTerminal(terminal) => {
+        write!(f, "{} windows can't be driven from the app", terminal)
+      }
+      AgentError::SendFailed(reason) => write!(f, "Could not send the command: {}", reason),
+      AgentError::PtyFailed(reason) => write!(f, "Agent terminal failed: {}", reason),
+      AgentError::LogFailed(reason) => write!(f, "Could not create log file: {}", reason),
+      AgentError::KillFailed { pid, reason } => write!(f, "Failed to kill process {}: {}", pid, reason),
+    }
+  }
+}
+
+impl std::error::Error for AgentError {}
+
+impl Serialize for AgentError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct("AgentError", 2)?;
+    state.serialize_field("kind", self.kind())?;
+    state.serialize_field("message", &self.to_string())?;
+    state.end()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn serializes_kind_and_message() {
+    let error = AgentError::InvalidWorkingDir("/nope".to_string());
+    assert_eq!(
+      serde_json::to_value(&error).unwrap(),
+      serde_json::json!({
+        "kind": "InvalidWorkingDir",
+        "message": "Working directory does not exist: /nope",
+      })
+    );
+  }
+}
@@ -0,0 +1,898 @@
+use std::process::Command;
+
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+use crate::{linux_geometry_args, linux_terminal_args, posix_runner};
+#[cfg(any(target_os = "macos", test))]
+use crate::{shell_quote, wait_with_timeout, MacTerminal, OnComplete};
+#[cfg(target_os = "macos")]
+use crate::DEFAULT_LAUNCH_TIMEOUT_MS;
+#[cfg(any(target_os = "windows", test))]
+use crate::{
+  spawn_in_new_console, windows_path_to_wsl, wsl_args, wt_geometry_args, WindowsShell, WindowsTerminal,
+};
+#[cfg(any(not(target_os = "windows"), test))]
+use crate::posix_delay_prefix;
+#[cfg(target_os = "windows")]
+use crate::{cmd_tee, cmd_word, ps_tee, ps_word};
+use crate::spawn_detached;
+use crate::{
+  env_export_prefix, exit_failure, output_with_timeout, posix_tee, shell_word, spawn_checked, tmux_args,
+  AgentError, AgentSpec, LaunchedTerminal,
+};
+
+/// A terminal that can be asked to run an agent.
+///
+/// `build_command` only describes the process to start, so each terminal's
+/// command line can be checked without opening any windows.
+pub trait TerminalLauncher {
+  /// Builds the process that opens this terminal running `spec.command`.
+  ///
+  /// `spec.command` is expected to have been validated and trimmed already.
+  fn build_command(&self, spec: &AgentSpec) -> Command;
+
+  /// Name reported for launched agents, accepted back as `terminal`.
+  fn name(&self) -> &str;
+
+  /// Starts the terminal. By default the built command is spawned detached,
+  /// checked for failing straight away, and kept as the child to watch.
+  fn launch(&self, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+    let child = spawn_checked(&mut self.build_command(spec), self.name())?;
+    Ok(LaunchedTerminal {
+      pid: child.id(),
+      terminal: self.name().to_string(),
+      tty: None,
+      child: Some(child),
+    })
+  }
+}
+
+/// The shell line a macOS terminal tab is given to run `spec`.
+#[cfg(any(target_os = "macos", test))]
+fn mac_shell_line(spec: &AgentSpec) -> String {
+  // Terminal runs the script in a fresh login shell, so variables have to be
+  // exported by the script rather than set on osascript. They come before
+  // the startup delay and the command, whose output goes to the log too.
+  let script = format!(
+    "{}{}{}",
+    env_export_prefix(&spec.env()),
+    posix_delay_prefix(spec.startup_delay_ms()),
+    posix_tee(&spec.command_line(shell_word), spec.log_path.as_deref())
+  );
+
+  // The tab's own shell stays open afterwards unless the script ends it
+  let script = match spec.on_complete() {
+    OnComplete::Interactive(None) => script,
+    OnComplete::Interactive(Some(shell)) => format!("{}; exec {}", script, shell_word(&shell)),
+    OnComplete::Close => format!("{}; exit", script),
+    OnComplete::Run(follow_up) => format!("{}; {}; exit", script, follow_up),
+  };
+
+  // Terminal.app opens new windows in the home directory regardless of the
+  // osascript process's cwd, so the script itself has to change directory.
+  match &spec.working_directory {
+    Some(dir) => format!("cd {} && {}", shell_quote(dir), script),
+    None => script,
+  }
+}
+
+/// Whether osascript failed because the user hasn't let this app automate
+/// the terminal: -1743 is errAEEventNotPermitted, and -1744 is what macOS
+/// returns while it is still waiting for them to answer the prompt.
+#[cfg(any(target_os = "macos", test))]
+fn automation_denied(message: &str) -> bool {
+  ["-1743", "-1744", "Not authorized to send Apple events"].iter().any(|sign| message.contains(sign))
+}
+
+/// The `.command` file that runs `line` through `shell` and then leaves
+/// `shell` open, as a tab opened through AppleScript would. It deletes
+/// itself, then writes its PID and tty to `info` so the agent can be
+/// tracked.
+#[cfg(any(target_os = "macos", test))]
+fn command_file(line: &str, shell: &str, info: &str) -> String {
+  let [program, flag] = posix_runner(shell, false);
+  let partial = format!("{}.partial", info);
+  let script = format!("{};exec {}", line, shell_word(shell));
+  [
+    "#!/bin/sh".to_string(),
+    "rm -f \"$0\"".to_string(),
+    format!("echo \"$$ $(tty)\" > {0} && mv {0} {1}", shell_quote(&partial), shell_quote(info)),
+    format!("exec {} {} {}", shell_word(program), flag, shell_quote(&script)),
+  ]
+  .join("\n")
+}
+
+/// Opens `spec` in `terminal` with `open -a` and a `.command` file, which
+/// needs no automation permission, for when the AppleScript launch was
+/// refused. There is no title, profile, tab or size this way.
+#[cfg(target_os = "macos")]
+fn open_command_file(terminal: MacTerminal, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+  use std::os::unix::fs::PermissionsExt;
+  use std::time::{Duration, Instant};
+  static NEXT_FILE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+  let seq = NEXT_FILE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  let base = std::env::temp_dir().join(format!("agentic-sync-{}-{}", std::process::id(), seq));
+  let file = base.with_extension("command");
+  let info = base.with_extension("info");
+  let shell = spec.shell();
+  let contents = command_file(&mac_shell_line(spec), &shell, &info.to_string_lossy());
+  std::fs::write(&file, contents)
+    .and_then(|()| std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o700)))
+    .map_err(|e| AgentError::SpawnFailed(format!("{}: {}", file.display(), e)))?;
+
+  let mut open = Command::new("open");
+  open.args(["-a", terminal.name()]).arg(&file);
+  let output = output_with_timeout(&mut open, DEFAULT_LAUNCH_TIMEOUT_MS)?;
+  if !output.status.success() {
+    let _ = std::fs::remove_file(&file);
+    return Err(AgentError::LaunchFailed(exit_failure("open", &output)));
+  }
+
+  let deadline = Instant::now() + Duration::from_millis(spec.timeout_ms());
+  loop {
+    let reported = std::fs::read_to_string(&info).ok();
+    if let Some((pid, tty)) = reported.as_deref().and_then(|info| info.trim().split_once(' ')) {
+      let _ = std::fs::remove_file(&info);
+      let pid = pid.parse().map_err(|_| AgentError::LaunchFailed(format!("bad PID {:?}", pid)))?;
+      return Ok(LaunchedTerminal {
+        pid,
+        terminal: terminal.name().to_string(),
+        tty: tty.starts_with("/dev/").then(|| tty.to_string()),
+        child: None,
+      });
+    }
+    if Instant::now() >= deadline {
+      return Err(AgentError::Timeout(spec.timeout_ms()));
+    }
+    std::thread::sleep(Duration::from_millis(50));
+  }
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl TerminalLauncher for MacTerminal {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    let mut command = Command::new("osascript");
+    let script = self.script(
+      &mac_shell_line(spec),
+      spec.title.as_deref(),
+      spec.profile.as_deref(),
+      spec.reuse_window(),
+      spec.geometry(),
+    );
+    command.args(["-e", &script]);
+    command
+  }
+
+  fn name(&self) -> &str {
+    match self {
+      MacTerminal::Terminal => "Terminal",
+      MacTerminal::ITerm => "iTerm",
+    }
+  }
+
+  fn launch(&self, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+    // osascript exits as soon as the terminal has accepted the script, so
+    // waiting on it is quick and is the only way to find out the script failed.
+    // The script's result is the tty of the new tab, printed on stdout.
+    let child = self
+      .build_command(spec)
+      .stdout(std::process::Stdio::piped())
+      .stderr(std::process::Stdio::piped())
+      .spawn()
+      .map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+    let pid = child.id();
+    let output = wait_with_timeout(child, spec.timeout_ms())?;
+
+    if output.status.success() {
+      let tty = String::from_utf8_lossy(&output.stdout).trim().to_string();
+      Ok(LaunchedTerminal {
+        pid,
+        terminal: self.name().to_string(),
+        tty: (!tty.is_empty()).then_some(tty),
+        child: None,
+      })
+    } else {
+      let message = exit_failure("osascript", &output);
+      if !automation_denied(&message) {
+        return Err(AgentError::LaunchFailed(message));
+      }
+      #[cfg(target_os = "macos")]
+      {
+        log::warn!("{} refused automation, opening the agent with open -a instead: {}", self.name(), message);
+        open_command_file(*self, spec).map_err(|e| {
+          log::warn!("Opening {} with open -a failed too: {}", self.name(), e);
+          AgentError::PermissionDenied(self.name().to_string())
+        })
+      }
+      #[cfg(not(target_os = "macos"))]
+      {
+        Err(AgentError::PermissionDenied(self.name().to_string()))
+      }
+    }
+  }
+}
+
+#[cfg(any(target_os = "windows", test))]
+impl TerminalLauncher for WindowsTerminal {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    let title = spec.title.as_deref();
+    let profile = spec.profile.as_deref();
+    let working_directory = spec.working_directory.as_deref();
+    let wsl = spec.wsl.unwrap_or(false);
+    // `start` can't size or place the console it opens
+    let geometry = match self {
+      WindowsTerminal::WindowsTerminal => wt_geometry_args(spec.geometry()),
+      WindowsTerminal::Cmd => Vec::new(),
+    };
+    let extra = [geometry, spec.extra_terminal_args().to_vec()].concat();
+
+    let (inner, dir) = if wsl {
+      // The directory is changed inside the distro, so the host doesn't need it
+      // Windows variables only reach the distro when listed in WSLENV
+      let log = spec.log_path.as_deref().map(windows_path_to_wsl);
+      let command = format!(
+        "{}{}",
+        env_export_prefix(&spec.env()),
+        posix_tee(&spec.command_line(shell_word), log.as_deref())
+      );
+      let delay_ms = spec.startup_delay_ms();
+      // bash is what runs inside the distro, whatever `shell` says for Windows
+      let follow_up = spec.on_complete().posix_follow_up("bash");
+      (wsl_args(spec.distro.as_deref(), working_directory, delay_ms, &command, follow_up.as_deref()), None)
+    } else {
+      (WindowsShell::for_spec(spec).args(spec), working_directory)
+    };
+    let (program, args) = match inner.split_first() {
+      Some((program, args)) if self.spawns_directly(spec) => (program.as_str(), args.to_vec()),
+      _ => self.launch_args(&inner, dir, title, profile, spec.reuse_window(), &extra),
+    };
+
+    // Both `wt` and `cmd /C start` hand the console off and exit straight away,
+    // so their exit status says nothing about the agent command itself; a
+    // shell spawned directly is the agent's own process.
+    let mut command = Command::new(program);
+    command.args(args).envs(spec.env());
+    if let Some(dir) = working_directory.filter(|_| !wsl) {
+      command.current_dir(dir);
+    }
+    command
+  }
+
+  fn name(&self) -> &str {
+    match self {
+      WindowsTerminal::WindowsTerminal => "wt",
+      WindowsTerminal::Cmd => "cmd",
+    }
+  }
+
+  /// A shell spawned directly writes to its console, so its stderr can't be
+  /// captured and its early exit is the agent's own, not a launch failure.
+  fn launch(&self, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+    let mut command = self.build_command(spec);
+    let child = if self.spawns_directly(spec) {
+      spawn_in_new_console(&mut command).map_err(|e| AgentError::SpawnFailed(e.to_string()))?
+    } else {
+      spawn_checked(&mut command, self.name())?
+    };
+    Ok(LaunchedTerminal {
+      pid: child.id(),
+      terminal: self.name().to_string(),
+      tty: None,
+      child: Some(child),
+    })
+  }
+}
+
+/// A Linux terminal emulator, named by program or path. kitty, WezTerm
+/// and Ghostty are driven the same way on macOS.
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+pub struct LinuxTerminal {
+  pub program: String,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+impl TerminalLauncher for LinuxTerminal {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    // The command is handed to the shell as positional parameters rather
+    // than spliced into the script, so its `;`, `&` or quotes can't change
+    // what the delay and fallback shell around it do. A command line is
+    // `eval`ed as a whole; an argument vector is run as-is.
+    let (program, argv) = match &spec.args {
+      Some(args) => ("\"$0\" \"$@\"", std::iter::once(&spec.command).chain(args).cloned().collect()),
+      None => ("eval \"$0\"", vec![spec.command.clone()]),
+    };
+    // Wait out the startup delay, then run the command, copying its output to
+    // the log if there is one
+    let shell = spec.shell();
+    let delay = posix_delay_prefix(spec.startup_delay_ms());
+    let mut script = format!("{}{}", delay, posix_tee(program, spec.log_path.as_deref()));
+    // Leave a shell open afterwards, or the emulator closes with the agent
+    if let Some(follow_up) = spec.on_complete().posix_follow_up(&shell) {
+      script = format!("{};{}", script, follow_up);
+    }
+
+    let geometry = linux_geometry_args(&self.program, spec.geometry());
+    let extra = [geometry, spec.extra_terminal_args().to_vec()].concat();
+
+    // Success means the emulator started; some (xterm, konsole) keep running
+    // until the window is closed, so their exit status can't be awaited here.
+    let mut command = Command::new(&self.program);
+    command
+      .args(linux_terminal_args(
+        &self.program,
+        spec.title.as_deref(),
+        spec.profile.as_deref(),
+        spec.reuse_window(),
+        &extra,
+        posix_runner(&shell, false),
+        &script,
+        &argv,
+      ))
+      .envs(spec.env());
+    if let Some(dir) = &spec.working_directory {
+      command.current_dir(dir);
+    }
+    command
+  }
+
+  fn name(&self) -> &str {
+    &self.program
+  }
+}
+
+/// A Linux terminal emulator installed as a flatpak, run as `flatpak run
+/// APP_ID` followed by the arguments `terminal` itself takes.
+#[cfg(any(target_os = "linux", test))]
+pub struct FlatpakTerminal {
+  pub terminal: String,
+  pub app_id: String,
+}
+
+#[cfg(any(target_os = "linux", test))]
+impl TerminalLauncher for FlatpakTerminal {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    let inner = LinuxTerminal { program: self.terminal.clone() }.build_command(spec);
+    let mut command = Command::new("flatpak");
+    command.args(["run", &self.app_id]).args(inner.get_args());
+    for (name, value) in inner.get_envs() {
+      if let Some(value) = value {
+        command.env(name, value);
+      }
+    }
+    if let Some(dir) = inner.get_current_dir() {
+      command.current_dir(dir);
+    }
+    command
+  }
+
+  fn name(&self) -> &str {
+    &self.terminal
+  }
+}
+
+/// A window in a tmux session, used instead of a terminal emulator.
+pub struct Tmux {
+  pub session: String,
+  /// Whether the session is already running, so a window is added to it
+  /// rather than a new session started.
+  pub session_exists: bool,
+}
+
+impl Tmux {
+  /// Checks whether `session` is already running.
+  pub fn for_session(session: &str, timeout_ms: u64) -> Result<Self, AgentError> {
+    let session_exists = output_with_timeout(
+      Command::new("tmux").args(["has-session", "-t", &format!("={}", session)]),
+      timeout_ms,
+    )?
+    .status
+    .success();
+    Ok(Tmux { session: session.to_string(), session_exists })
+  }
+}
+
+impl TerminalLauncher for Tmux {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    let command = format!(
+      "{}{}",
+      env_export_prefix(&spec.env()),
+      posix_tee(&spec.command_line(shell_word), spec.log_path.as_deref())
+    );
+    let follow_up = spec.on_complete().posix_follow_up(&spec.shell());
+    let mut tmux = Command::new("tmux");
+    tmux.args(tmux_args(
+      &self.session,
+      self.session_exists,
+      &command,
+      follow_up.as_deref(),
+      spec.working_directory.as_deref(),
+      spec.title.as_deref(),
+    ));
+    tmux
+  }
+
+  fn name(&self) -> &str {
+    "tmux"
+  }
+
+  fn launch(&self, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+    let output = output_with_timeout(&mut self.build_command(spec), spec.timeout_ms())?;
+    if !output.status.success() {
+      return Err(AgentError::LaunchFailed(exit_failure("tmux", &output)));
+    }
+
+    let pid = String::from_utf8_lossy(&output.stdout)
+      .trim()
+      .parse()
+      .map_err(|_| AgentError::LaunchFailed("tmux did not report the pane's PID".to_string()))?;
+    Ok(LaunchedTerminal { pid, terminal: self.name().to_string(), tty: None, child: None })
+  }
+}
+
+/// Runs the command directly, without a terminal, for agents that don't need
+/// one. Its output is piped back for `launch_agent` to stream as events.
+///
+/// There is no tty and stdin is closed, so agents that prompt or draw a
+/// full-screen interface won't work this way.
+pub struct Headless;
+
+impl TerminalLauncher for Headless {
+  fn build_command(&self, spec: &AgentSpec) -> Command {
+    #[cfg(not(target_os = "windows"))]
+    let mut command = {
+      // A login shell picks up the user's PATH, which apps started from the
+      // desktop don't always have. As on Linux, the command is a parameter.
+      let shell = spec.shell();
+      let runner = posix_runner(&shell, true);
+      let (program, argv) = match &spec.args {
+        Some(args) => ("\"$0\" \"$@\"", std::iter::once(&spec.command).chain(args).cloned().collect()),
+        None => ("eval \"$0\"", vec![spec.command.clone()]),
+      };
+      let mut command = Command::new(runner[0]);
+      command.args([runner[1], &posix_tee(program, spec.log_path.as_deref())]).args(argv);
+      command
+    };
+
+    // spawn_detached keeps the shell's console window hidden
+    #[cfg(target_os = "windows")]
+    let mut command = {
+      let log = spec.log_path.as_deref();
+      match WindowsShell::for_spec(spec) {
+        WindowsShell::Cmd => {
+          let mut command = Command::new("cmd");
+          command.args(["/C", &cmd_tee(&spec.command_line(cmd_word), log)]);
+          command
+        }
+        shell => {
+          let line = match &spec.args {
+            Some(_) => format!("& {}", spec.command_line(ps_word)),
+            None => spec.command.clone(),
+          };
+          let mut command = Command::new(shell.program());
+          command.args(["-NoProfile", "-Command", &ps_tee(&line, log)]);
+          command
+        }
+      }
+    };
+
+    command
+      .envs(spec.env())
+      .stdin(std::process::Stdio::null())
+      .stdout(std::process::Stdio::piped())
+      .stderr(std::process::Stdio::piped());
+    if let Some(dir) = &spec.working_directory {
+      command.current_dir(dir);
+    }
+    command
+  }
+
+  fn name(&self) -> &str {
+    "headless"
+  }
+
+  fn launch(&self, spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+    // Nothing stands between us and the agent, so a failure shows up in its
+    // output and exit code rather than as a launch error
+    let child = spawn_detached(&mut self.build_command(spec))
+      .map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+    Ok(LaunchedTerminal { pid: child.id(), terminal: self.name().to_string(), tty: None, child: Some(child) })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, ffi::OsStr, path::Path};
+
+  use super::*;
+
+  fn args(command: &Command) -> Vec<String> {
+    command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+  }
+
+  fn spec(command: &str) -> AgentSpec {
+    AgentSpec { command: command.to_string(), shell: Some("bash".to_string()), ..AgentSpec::default() }
+  }
+
+  #[test]
+  fn linux_terminal_runs_script_in_working_directory() {
+    let spec = AgentSpec {
+      working_directory: Some("/work".to_string()),
+      env: Some(HashMap::from([("FOO".to_string(), "bar".to_string())])),
+      ..spec("claude")
+    };
+    let command = LinuxTerminal { program: "gnome-terminal".to_string() }.build_command(&spec);
+
+    assert_eq!(command.get_program(), "gnome-terminal");
+    assert_eq!(args(&command), ["--wait", "--", "bash", "-ic", "sleep 2 && eval \"$0\";bash", "claude"]);
+    assert_eq!(command.get_current_dir(), Some(Path::new("/work")));
+    assert!(command.get_envs().any(|(name, value)| name == "FOO" && value == Some(OsStr::new("bar"))));
+  }
+
+  #[test]
+  fn linux_terminal_passes_argument_vector_through() {
+    let spec = AgentSpec {
+      args: Some(vec!["--prompt".to_string(), "fix \"it\"; now".to_string()]),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let command = LinuxTerminal { program: "kitty".to_string() }.build_command(&spec);
+    assert_eq!(
+      args(&command),
+      ["bash", "-ic", "\"$0\" \"$@\";bash", "claude", "--prompt", "fix \"it\"; now"]
+    );
+  }
+
+  #[test]
+  fn linux_terminal_runs_non_posix_shells_via_sh() {
+    let spec = AgentSpec {
+      shell: Some("/usr/bin/fish".to_string()),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let command = LinuxTerminal { program: "xterm".to_string() }.build_command(&spec);
+    assert_eq!(args(&command), ["-e", "/bin/sh", "-c", "eval \"$0\";/usr/bin/fish", "claude"]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn linux_terminal_runs_command_lines_unchanged() {
+    let cases = [
+      ("echo $(echo sub)", "sub\n"),
+      ("echo a; echo b", "a\nb\n"),
+      (r#"echo "it's \"quoted\"""#, "it's \"quoted\"\n"),
+      ("false && echo no || echo yes", "yes\n"),
+      ("echo one # trailing comment", "one\n"),
+    ];
+    for (line, expected) in cases {
+      let spec = AgentSpec { shell: Some("sh".to_string()), startup_delay_ms: Some(0), ..spec(line) };
+      // kitty takes the runner and script without any flags of its own
+      let argv = args(&LinuxTerminal { program: "kitty".to_string() }.build_command(&spec));
+      assert!(!argv[2].contains("echo"), "command spliced into {:?}", argv[2]);
+
+      let output = Command::new(&argv[0]).args(&argv[1..]).stdin(std::process::Stdio::null()).output().unwrap();
+      assert_eq!(String::from_utf8_lossy(&output.stdout), expected, "for {:?}", line);
+    }
+  }
+
+  /// Runs `sh -c script` as if it were a terminal emulator.
+  #[cfg(unix)]
+  struct FakeTerminal(&'static str);
+
+  #[cfg(unix)]
+  impl TerminalLauncher for FakeTerminal {
+    fn build_command(&self, _spec: &AgentSpec) -> Command {
+      let mut command = Command::new("sh");
+      command.args(["-c", self.0]);
+      command
+    }
+
+    fn name(&self) -> &str {
+      "fake"
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn launch_reports_stderr_of_terminals_that_fail_at_once() {
+    let error = FakeTerminal("echo 'cannot open display' >&2; exit 1").launch(&spec("claude")).unwrap_err();
+    assert_eq!(
+      error,
+      AgentError::LaunchFailed("fake exited with exit status: 1: cannot open display".to_string())
+    );
+
+    let mut launched = FakeTerminal("echo 'harmless warning' >&2; sleep 30").launch(&spec("claude")).unwrap();
+    let mut child = launched.child.take().unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn flatpak_terminal_runs_the_app_with_the_terminals_own_args() {
+    let spec =
+      AgentSpec { working_directory: Some("/work".to_string()), startup_delay_ms: Some(0), ..spec("claude") };
+    let terminal =
+      FlatpakTerminal { terminal: "gnome-terminal".to_string(), app_id: "org.gnome.Terminal".to_string() };
+    let command = terminal.build_command(&spec);
+    assert_eq!(command.get_program(), "flatpak");
+    assert_eq!(
+      args(&command),
+      ["run", "org.gnome.Terminal", "--wait", "--", "bash", "-ic", "eval \"$0\";bash", "claude"]
+    );
+    assert_eq!(command.get_current_dir(), Some(Path::new("/work")));
+    assert_eq!(terminal.name(), "gnome-terminal");
+  }
+
+  #[test]
+  fn headless_runs_command_without_a_terminal() {
+    let spec = AgentSpec {
+      working_directory: Some("/".to_string()),
+      env: Some(HashMap::from([("FOO".to_string(), "bar".to_string())])),
+      // Not a login shell, so the test doesn't depend on this machine's profile
+      shell: Some("sh".to_string()),
+      ..spec("echo \"$FOO\" $(pwd); echo oops >&2")
+    };
+    let output = Headless.build_command(&spec).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "bar /\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+  }
+
+  #[test]
+  fn mac_terminal_changes_directory_inside_the_script() {
+    let spec = AgentSpec {
+      working_directory: Some("/my work".to_string()),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let command = MacTerminal::Terminal.build_command(&spec);
+
+    assert_eq!(command.get_program(), "osascript");
+    let args = args(&command);
+    assert_eq!(args[0], "-e");
+    assert!(args[1].contains("do script \"cd '/my work' && claude\""));
+    assert_eq!(MacTerminal::ITerm.name(), "iTerm");
+  }
+
+  #[test]
+  fn automation_denied_spots_the_apple_event_errors() {
+    assert!(automation_denied("execution error: Not authorized to send Apple events to Terminal. (-1743)"));
+    assert!(automation_denied("execution error: (-1744)"));
+    assert!(!automation_denied("syntax error: Expected end of line (-2741)"));
+  }
+
+  #[test]
+  fn command_file_reports_its_pid_and_removes_itself() {
+    let base = std::env::temp_dir().join(format!("agentic-sync-command-{}", std::process::id()));
+    let info = base.with_extension("info");
+    let out = base.with_extension("out");
+    let line = format!("echo ran > {}", shell_quote(&out.to_string_lossy()));
+    let contents = command_file(&line, "sh", &info.to_string_lossy());
+    assert!(contents.starts_with("#!/bin/sh\nrm -f \"$0\"\n"));
+    assert!(contents.ends_with(";exec sh'"));
+    #[cfg(unix)]
+    {
+      let file = base.with_extension("command");
+      std::fs::write(&file, contents).unwrap();
+
+      let status = Command::new("sh").arg(&file).stdin(std::process::Stdio::null()).status().unwrap();
+      assert!(status.success());
+      assert!(!file.exists());
+      assert_eq!(std::fs::read_to_string(&out).unwrap(), "ran\n");
+      let reported = std::fs::read_to_string(&info).unwrap();
+      let (pid, _tty) = reported.trim().split_once(' ').unwrap();
+      assert!(pid.parse::<u32>().is_ok());
+      let _ = std::fs::remove_file(&info);
+      let _ = std::fs::remove_file(&out);
+    }
+  }
+
+  #[test]
+  fn windows_terminal_wraps_cmd_or_wsl() {
+    let cmd_spec = AgentSpec { startup_delay_ms: Some(0), ..spec("claude") };
+    let command = WindowsTerminal::Cmd.build_command(&cmd_spec);
+    assert_eq!(command.get_program(), "cmd");
+    assert_eq!(args(&command), ["/C", "start", "cmd", "/k", "claude"]);
+
+    let wsl_spec = AgentSpec {
+      wsl: Some(true),
+      working_directory: Some(r"C:\work".to_string()),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let command = WindowsTerminal::WindowsTerminal.build_command(&wsl_spec);
+    assert_eq!(command.get_program(), "wt");
+    assert_eq!(
+      args(&command),
+      ["new-tab", "wsl.exe", "--", "bash", "-lc", r"cd '/mnt/c/work' && claude\; bash"]
+    );
+    assert_eq!(command.get_current_dir(), None);
+
+    let pwsh_spec = AgentSpec {
+      shell: Some("pwsh".to_string()),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let command = WindowsTerminal::WindowsTerminal.build_command(&pwsh_spec);
+    assert_eq!(args(&command), ["new-tab", "pwsh", "-NoExit", "-Command", "claude"]);
+  }
+
+  #[test]
+  fn direct_console_runs_the_shell_without_start() {
+    let direct = AgentSpec {
+      direct_console: Some(true),
+      startup_delay_ms: Some(0),
+      working_directory: Some(r"C:\work".to_string()),
+      extra_terminal_args: Some(vec!["/max".to_string()]),
+      ..spec("claude")
+    };
+    let command = WindowsTerminal::Cmd.build_command(&direct);
+    assert_eq!(command.get_program(), "cmd");
+    assert_eq!(args(&command), ["/k", "claude"]);
+    assert_eq!(command.get_current_dir(), Some(Path::new(r"C:\work")));
+
+    let wsl = WindowsTerminal::Cmd.build_command(&AgentSpec { wsl: Some(true), ..direct.clone() });
+    assert_eq!(wsl.get_program(), "wsl.exe");
+    assert_eq!(args(&wsl), ["--", "bash", "-lc", "cd '/mnt/c/work' && claude; bash"]);
+    assert_eq!(wsl.get_current_dir(), None);
+
+    // Windows Terminal always opens the tab itself
+    let wt = WindowsTerminal::WindowsTerminal.build_command(&direct);
+    assert_eq!(wt.get_program(), "wt");
+  }
+
+  // One launch per platform with every option that shapes the command line
+  // set at once, so their interactions are pinned down too.
+
+  #[test]
+  fn linux_build_combines_title_delay_directory_and_specials() {
+    let spec = AgentSpec {
+      title: Some(r#"Agent "1""#.to_string()),
+      startup_delay_ms: Some(1500),
+      working_directory: Some("/work dir".to_string()),
+      ..spec(r#"echo "$HOME"; ls | wc -l"#)
+    };
+    let command = LinuxTerminal { program: "gnome-terminal".to_string() }.build_command(&spec);
+    assert_eq!(
+      args(&command),
+      [
+        "--title",
+        r#"Agent "1""#,
+        "--wait",
+        "--",
+        "bash",
+        "-ic",
+        r#"sleep 1.5 && eval "$0";bash"#,
+        r#"echo "$HOME"; ls | wc -l"#,
+      ]
+    );
+    assert_eq!(command.get_current_dir(), Some(Path::new("/work dir")));
+  }
+
+  #[test]
+  fn macos_build_combines_title_delay_directory_and_specials() {
+    let spec = AgentSpec {
+      title: Some("Planner".to_string()),
+      startup_delay_ms: Some(2000),
+      working_directory: Some("/my work".to_string()),
+      ..spec(r#"echo "hi" \ done"#)
+    };
+    let command = MacTerminal::Terminal.build_command(&spec);
+    assert_eq!(command.get_program(), "osascript");
+    assert_eq!(
+      args(&command),
+      [
+        "-e",
+        concat!(
+          "tell application \"Terminal\"\n",
+          r#"  set newTab to do script "cd '/my work' && sleep 2 && echo \"hi\" \\ done""#,
+          "\n",
+          "  set custom title of newTab to \"Planner\"\n",
+          "  tty of newTab\n",
+          "end tell",
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn windows_build_combines_title_delay_directory_and_specials() {
+    let spec = AgentSpec {
+      title: Some("A&B".to_string()),
+      startup_delay_ms: Some(2000),
+      working_directory: Some(r"C:\work".to_string()),
+      shell: None,
+      ..spec("echo a; echo b")
+    };
+    let command = WindowsTerminal::WindowsTerminal.build_command(&spec);
+    assert_eq!(command.get_program(), "wt");
+    assert_eq!(
+      args(&command),
+      [
+        "new-tab",
+        "--title",
+        "A&B",
+        "-d",
+        r"C:\work",
+        "cmd",
+        "/k",
+        r"title A^&B && timeout /t 2 && echo a\; echo b",
+      ]
+    );
+    assert_eq!(command.get_current_dir(), Some(Path::new(r"C:\work")));
+  }
+
+  #[test]
+  fn tmux_exports_env_before_the_command() {
+    let spec = AgentSpec {
+      env: Some(HashMap::from([("FOO".to_string(), "a b".to_string())])),
+      ..spec("claude")
+    };
+    let tmux = Tmux { session: "agents".to_string(), session_exists: true };
+    let command = tmux.build_command(&spec);
+
+    assert_eq!(command.get_program(), "tmux");
+    assert_eq!(args(&command).last().unwrap(), "export FOO='a b' && claude; bash");
+  }
+
+  #[test]
+  fn keep_open_off_ends_the_shell_with_the_agent() {
+    let spec = AgentSpec { keep_open: Some(false), startup_delay_ms: Some(0), ..spec("claude") };
+
+    let linux = LinuxTerminal { program: "kitty".to_string() }.build_command(&spec);
+    assert_eq!(args(&linux), ["bash", "-ic", "eval \"$0\"", "claude"]);
+    let mac = MacTerminal::ITerm.build_command(&spec);
+    assert!(args(&mac)[1].contains("write text \"claude; exit\""));
+    let cmd = WindowsTerminal::Cmd.build_command(&spec);
+    assert_eq!(args(&cmd), ["/C", "start", "cmd", "/c", "claude"]);
+    let pwsh_spec = AgentSpec { shell: Some("pwsh".to_string()), ..spec.clone() };
+    let pwsh = WindowsTerminal::Cmd.build_command(&pwsh_spec);
+    assert_eq!(args(&pwsh), ["/C", "start", "pwsh", "-Command", "claude"]);
+    let wsl = WindowsTerminal::Cmd.build_command(&AgentSpec { wsl: Some(true), ..spec.clone() });
+    assert_eq!(args(&wsl).last().unwrap(), "claude");
+    let tmux = Tmux { session: "agents".to_string(), session_exists: true }.build_command(&spec);
+    assert_eq!(args(&tmux).last().unwrap(), "claude");
+  }
+
+  #[test]
+  fn on_complete_sets_what_runs_after_the_agent() {
+    let after = |on_complete| AgentSpec {
+      on_complete: Some(on_complete),
+      startup_delay_ms: Some(0),
+      ..spec("claude")
+    };
+    let fish = after(OnComplete::Interactive(Some("fish".to_string())));
+    let close = after(OnComplete::Close);
+    let run = after(OnComplete::Run("notify-send done".to_string()));
+    // on_complete wins over keep_open
+    let run = AgentSpec { keep_open: Some(false), ..run };
+
+    let kitty = LinuxTerminal { program: "kitty".to_string() };
+    let linux = |spec: &AgentSpec| args(&kitty.build_command(spec))[2].clone();
+    assert_eq!(linux(&fish), "eval \"$0\";fish");
+    assert_eq!(linux(&close), "eval \"$0\"");
+    assert_eq!(linux(&run), "eval \"$0\";notify-send done");
+
+    assert_eq!(mac_shell_line(&fish), "claude; exec fish");
+    assert_eq!(mac_shell_line(&close), "claude; exit");
+    assert_eq!(mac_shell_line(&run), "claude; notify-send done; exit");
+    assert_eq!(mac_shell_line(&spec("claude")), "sleep 2 && claude");
+
+    let cmd = |spec: &AgentSpec| WindowsShell::Cmd.args(spec)[1..].to_vec();
+    assert_eq!(cmd(&fish), ["/c", "claude & fish"]);
+    assert_eq!(cmd(&close), ["/c", "claude"]);
+    assert_eq!(cmd(&run), ["/c", "claude & notify-send done"]);
+    assert_eq!(WindowsShell::Pwsh.args(&run), ["pwsh", "-Command", "claude; notify-send done"]);
+    let interactive = after(OnComplete::Interactive(None));
+    assert_eq!(WindowsShell::Pwsh.args(&interactive), ["pwsh", "-NoExit", "-Command", "claude"]);
+
+    let wsl = |spec: &AgentSpec| {
+      let spec = AgentSpec { wsl: Some(true), ..spec.clone() };
+      args(&WindowsTerminal::Cmd.build_command(&spec)).last().unwrap().clone()
+    };
+    assert_eq!(wsl(&interactive), "claude; bash");
+    assert_eq!(wsl(&fish), "claude; fish");
+    assert_eq!(wsl(&close), "claude");
+    let tmux = Tmux { session: "agents".to_string(), session_exists: true }.build_command(&run);
+    assert_eq!(args(&tmux).last().unwrap(), "claude; notify-send done");
+  }
+}
@@ -3,73 +3,4829 @@
   windows_subsystem = "windows"
 )]
 
-use std::{process::Command, thread, time::Duration};
+mod audit;
+mod config;
+mod error;
+mod launcher;
+mod pty;
+mod registry;
 
-#[tauri::command]
-fn launch_agent_terminal(command: String) -> Result<String, String> {
-  // Platform-specific terminal launching
+use std::{
+  collections::HashMap,
+  path::Path,
+  process::{Child, Command},
+  thread,
+};
+
+use log::{debug, info, trace, warn};
+use tauri::Manager;
+
+use config::TerminalConfig;
+use error::AgentError;
+#[cfg(any(target_os = "linux", test))]
+use launcher::FlatpakTerminal;
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+use launcher::LinuxTerminal;
+use launcher::{Headless, TerminalLauncher, Tmux};
+use registry::{AgentInfo, AgentRegistry, AgentStatus};
+
+/// Quotes `input` as a single POSIX shell word.
+///
+/// The value is wrapped in single quotes, and any embedded single quote is
+/// written as `'\''` (close quote, escaped quote, reopen quote).
+fn shell_quote(input: &str) -> String {
+  format!("'{}'", input.replace('\'', "'\\''"))
+}
+
+/// Writes `arg` as a POSIX shell word, quoting it only if the shell would
+/// otherwise interpret part of it.
+fn shell_word(arg: &str) -> String {
+  let plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+  if plain {
+    arg.to_string()
+  } else {
+    shell_quote(arg)
+  }
+}
+
+/// Writes `arg` as a single argument on a cmd command line, using the
+/// double-quote convention that Windows programs parse their arguments with:
+/// backslashes are only special when they come before a `"`, so those runs
+/// (and the one before the closing quote) are doubled.
+#[cfg(any(target_os = "windows", test))]
+fn cmd_word(arg: &str) -> String {
+  let plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,\\".contains(c));
+  if plain {
+    return arg.to_string();
+  }
+
+  let mut quoted = String::from("\"");
+  let mut backslashes = 0;
+  for c in arg.chars() {
+    match c {
+      '\\' => backslashes += 1,
+      '"' => {
+        quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+        quoted.push('"');
+        backslashes = 0;
+      }
+      _ => {
+        quoted.push_str(&"\\".repeat(backslashes));
+        quoted.push(c);
+        backslashes = 0;
+      }
+    }
+  }
+  quoted.push_str(&"\\".repeat(backslashes * 2));
+  quoted.push('"');
+  quoted
+}
+
+/// Builds `export KEY=value && ` for each variable, in name order, for
+/// launchers whose shell doesn't inherit our environment (macOS tabs, tmux
+/// panes and WSL).
+fn env_export_prefix(env: &HashMap<String, String>) -> String {
+  let mut vars: Vec<_> = env.iter().collect();
+  vars.sort();
+  vars
+    .into_iter()
+    .map(|(name, value)| format!("export {}={} && ", name, shell_quote(value)))
+    .collect()
+}
+
+/// Checks that every variable name can be exported from a POSIX shell.
+fn validate_env(env: &HashMap<String, String>) -> Result<(), AgentError> {
+  let is_identifier = |name: &str| {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+      && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+  };
+  match env.keys().find(|name| !is_identifier(name)) {
+    Some(name) => Err(AgentError::InvalidEnvVar(name.clone())),
+    None => Ok(()),
+  }
+}
+
+/// Escapes a string so it can be embedded inside an AppleScript string literal.
+///
+/// AppleScript treats `\` and `"` as special inside `"..."`, so both are
+/// backslash-escaped. Everything else (including `$` and `;`) is passed through
+/// untouched because it is meant for the shell that Terminal runs.
+#[cfg(any(target_os = "macos", test))]
+fn escape_for_applescript(input: &str) -> String {
+  let mut escaped = String::with_capacity(input.len());
+  for c in input.chars() {
+    match c {
+      '\\' => escaped.push_str("\\\\"),
+      '"' => escaped.push_str("\\\""),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// Wraps a POSIX shell command so its output is also appended to `log`.
+///
+/// The command's stdout then goes to a pipe rather than the terminal, so
+/// programs that check for a tty may switch to plain output.
+fn posix_tee(command: &str, log: Option<&str>) -> String {
+  match log {
+    Some(log) => format!("{{ {}; }} 2>&1 | tee -a {}", command, shell_quote(log)),
+    None => command.to_string(),
+  }
+}
+
+/// Wraps a cmd command so its output is also appended to `log`. cmd has no
+/// `tee` of its own, so PowerShell's `Tee-Object` does the copying.
+#[cfg(any(target_os = "windows", test))]
+fn cmd_tee(command: &str, log: Option<&str>) -> String {
+  match log {
+    Some(log) => format!(
+      "({}) 2>&1 | powershell -NoProfile -Command \"$input | Tee-Object -FilePath {} -Append\"",
+      command,
+      ps_quote(log)
+    ),
+    None => command.to_string(),
+  }
+}
+
+/// Wraps a PowerShell command so all of its output streams are also appended
+/// to `log`.
+#[cfg(any(target_os = "windows", test))]
+fn ps_tee(command: &str, log: Option<&str>) -> String {
+  match log {
+    Some(log) => format!("& {{ {} }} *>&1 | Tee-Object -FilePath {} -Append", command, ps_quote(log)),
+    None => command.to_string(),
+  }
+}
+
+/// Picks a fresh, timestamped log file in `dir`, creating the directory.
+fn new_log_path(dir: &Path) -> Result<String, AgentError> {
+  static NEXT_LOG: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+  std::fs::create_dir_all(dir)
+    .map_err(|e| AgentError::LogFailed(format!("{}: {}", dir.display(), e)))?;
+  let millis = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis())
+    .unwrap_or(0);
+  let seq = NEXT_LOG.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  Ok(dir.join(format!("agent-{}-{}.log", millis, seq)).to_string_lossy().into_owned())
+}
+
+/// Terminals that are run through their own command line on macOS, as on
+/// Linux, rather than through AppleScript, with where their app bundle keeps
+/// the binary.
+#[cfg(any(target_os = "macos", test))]
+const MAC_CLI_TERMINALS: &[(&str, &str)] = &[
+  ("kitty", "kitty.app/Contents/MacOS/kitty"),
+  ("wezterm", "WezTerm.app/Contents/MacOS/wezterm"),
+  ("ghostty", "Ghostty.app/Contents/MacOS/ghostty"),
+];
+
+/// The Applications folders apps are installed in, system-wide and per user.
+#[cfg(target_os = "macos")]
+fn mac_app_dirs() -> Vec<std::path::PathBuf> {
+  let home_apps = std::env::var_os("HOME").map(|home| Path::new(&home).join("Applications"));
+  std::iter::once(std::path::PathBuf::from("/Applications")).chain(home_apps).collect()
+}
+
+/// Finds `program` on `PATH`, or else `bundle_binary` inside one of
+/// `app_dirs`, since apps dragged into Applications don't put their CLI on
+/// `PATH`.
+#[cfg(any(target_os = "macos", test))]
+fn find_mac_cli_terminal(
+  program: &str,
+  bundle_binary: &str,
+  app_dirs: &[std::path::PathBuf],
+) -> Option<String> {
+  find_on_path(program)
+    .or_else(|| app_dirs.iter().map(|dir| dir.join(bundle_binary)).find(|binary| binary.is_file()))
+    .map(|binary| binary.to_string_lossy().into_owned())
+}
+
+/// Returns the launcher for the macOS terminal called `name`, or `None` if it
+/// isn't installed. Fails for a name that isn't a macOS terminal.
+#[cfg(target_os = "macos")]
+fn mac_launcher(name: &str) -> Result<Option<Box<dyn TerminalLauncher>>, AgentError> {
+  let lower = name.trim().to_ascii_lowercase();
+  let cli_terminal =
+    MAC_CLI_TERMINALS.iter().find(|(program, _)| lower == *program || lower == format!("{}.app", program));
+  if let Some((program, bundle_binary)) = cli_terminal {
+    let found = find_mac_cli_terminal(program, bundle_binary, &mac_app_dirs());
+    return Ok(found.map(|program| Box::new(LinuxTerminal { program }) as Box<dyn TerminalLauncher>));
+  }
+  let terminal = MacTerminal::from_name(name).ok_or_else(|| AgentError::UnsupportedTerminal(name.to_string()))?;
+  Ok(terminal.is_installed().then(|| Box::new(terminal) as Box<dyn TerminalLauncher>))
+}
+
+/// How long launched commands wait for the terminal to initialize by default.
+const DEFAULT_STARTUP_DELAY_MS: u64 = 2000;
+
+/// Returns the `sleep` prefix for POSIX shells, or nothing for a zero delay.
+///
+/// Both GNU and BSD `sleep` accept fractional seconds, so sub-second delays
+/// are kept rather than rounded.
+fn posix_delay_prefix(delay_ms: u64) -> String {
+  match (delay_ms / 1000, delay_ms % 1000) {
+    (0, 0) => String::new(),
+    (secs, 0) => format!("sleep {} && ", secs),
+    (secs, millis) => {
+      let fraction = format!("{:03}", millis);
+      format!("sleep {}.{} && ", secs, fraction.trim_end_matches('0'))
+    }
+  }
+}
+
+/// Returns the `timeout` prefix for cmd, or nothing for a zero delay.
+///
+/// `timeout /t` only takes whole seconds, so the delay is rounded up.
+#[cfg(any(target_os = "windows", test))]
+fn cmd_delay_prefix(delay_ms: u64) -> String {
+  match delay_ms.div_ceil(1000) {
+    0 => String::new(),
+    secs => format!("timeout /t {} && ", secs),
+  }
+}
+
+/// Terminal applications that can be driven through AppleScript on macOS.
+#[cfg(any(target_os = "macos", test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacTerminal {
+  Terminal,
+  ITerm,
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl MacTerminal {
+  /// Environment variable that overrides the default terminal when the caller
+  /// doesn't pick one explicitly.
   #[cfg(target_os = "macos")]
+  const DEFAULT_ENV: &'static str = "AGENTIC_SYNC_MACOS_TERMINAL";
+
+  fn from_name(name: &str) -> Option<Self> {
+    match name.trim().to_ascii_lowercase().as_str() {
+      "terminal" | "terminal.app" => Some(MacTerminal::Terminal),
+      "iterm" | "iterm2" | "iterm.app" => Some(MacTerminal::ITerm),
+      _ => None,
+    }
+  }
+
+  /// Terminal.app ships with macOS; iTerm has to be found in an Applications folder.
+  #[cfg(target_os = "macos")]
+  fn is_installed(self) -> bool {
+    match self {
+      MacTerminal::Terminal => true,
+      MacTerminal::ITerm => mac_app_dirs().iter().any(|dir| dir.join("iTerm.app").is_dir()),
+    }
+  }
+
+  /// Builds the AppleScript that opens a new window running `command`,
+  /// optionally titled `title`. The script evaluates to the new tab's tty.
+  ///
+  /// With `reuse_window` the command gets a new tab in the front window when
+  /// there is one. Terminal.app has no scripting command for tabs, so there
+  /// the tab is opened with a Cmd-T keystroke, which needs accessibility
+  /// access; without it the command gets a new window after all.
+  ///
+  /// `profile` is an iTerm profile or a Terminal.app settings set.
+  ///
+  /// `geometry` resizes the tab to its rows and columns, then moves its
+  /// window by setting its bounds, keeping the size it ended up with.
+  fn script(
+    self,
+    command: &str,
+    title: Option<&str>,
+    profile: Option<&str>,
+    reuse_window: bool,
+    geometry: Option<WindowGeometry>,
+  ) -> String {
+    let command = escape_for_applescript(command);
+    let title = title.map(escape_for_applescript);
+    let profile = profile.map(escape_for_applescript);
+    let iterm_profile = match &profile {
+      Some(profile) => format!("profile \"{}\"", profile),
+      None => "default profile".to_string(),
+    };
+    let mut lines = match (self, reuse_window) {
+      (MacTerminal::Terminal, false) => vec![
+        "tell application \"Terminal\"".to_string(),
+        format!("  set newTab to do script \"{}\"", command),
+      ],
+      (MacTerminal::Terminal, true) => vec![
+        "tell application \"Terminal\"".to_string(),
+        "  set newTab to missing value".to_string(),
+        "  if (count of windows) > 0 then".to_string(),
+        "    activate".to_string(),
+        "    try".to_string(),
+        "      tell application \"System Events\" to keystroke \"t\" using command down".to_string(),
+        "      delay 0.3".to_string(),
+        format!("      set newTab to do script \"{}\" in front window", command),
+        "    end try".to_string(),
+        "  end if".to_string(),
+        "  if newTab is missing value then".to_string(),
+        format!("    set newTab to do script \"{}\"", command),
+        "  end if".to_string(),
+      ],
+      (MacTerminal::ITerm, _) => {
+        let mut lines = vec!["tell application \"iTerm\"".to_string(), "  activate".to_string()];
+        if reuse_window {
+          lines.extend([
+            "  if (count of windows) > 0 then".to_string(),
+            "    set newWindow to current window".to_string(),
+            format!("    tell newWindow to create tab with {}", iterm_profile),
+            "  else".to_string(),
+            format!("    set newWindow to (create window with {})", iterm_profile),
+            "  end if".to_string(),
+          ]);
+        } else {
+          lines.push(format!("  set newWindow to (create window with {})", iterm_profile));
+        }
+        lines.push(format!("  tell current session of newWindow to write text \"{}\"", command));
+        lines
+      }
+    };
+    if let (MacTerminal::Terminal, Some(profile)) = (self, &profile) {
+      lines.push(format!("  set current settings of newTab to settings set \"{}\"", profile));
+    }
+    match (self, title) {
+      (MacTerminal::Terminal, Some(title)) => {
+        lines.push(format!("  set custom title of newTab to \"{}\"", title));
+      }
+      (MacTerminal::ITerm, Some(title)) => {
+        lines.push(format!("  tell current session of newWindow to set name to \"{}\"", title));
+      }
+      (_, None) => {}
+    }
+    if let Some((cols, rows)) = geometry.and_then(|geometry| geometry.size) {
+      lines.extend(match self {
+        MacTerminal::Terminal => [
+          format!("  set number of columns of newTab to {}", cols),
+          format!("  set number of rows of newTab to {}", rows),
+        ],
+        MacTerminal::ITerm => [
+          format!("  tell current session of newWindow to set columns to {}", cols),
+          format!("  tell current session of newWindow to set rows to {}", rows),
+        ],
+      });
+    }
+    if let Some(WindowPosition { x, y }) = geometry.and_then(|geometry| geometry.position) {
+      let window = match self {
+        MacTerminal::Terminal => "front window",
+        MacTerminal::ITerm => "newWindow",
+      };
+      lines.extend([
+        format!("  set {{x1, y1, x2, y2}} to bounds of {}", window),
+        format!("  set bounds of {} to {{{x}, {y}, {x} + x2 - x1, {y} + y2 - y1}}", window, x = x, y = y),
+      ]);
+    }
+    lines.push(match self {
+      MacTerminal::Terminal => "  tty of newTab".to_string(),
+      MacTerminal::ITerm => "  tty of current session of newWindow".to_string(),
+    });
+    lines.push("end tell".to_string());
+    lines.join("\n")
+  }
+
+  /// Builds the AppleScript that brings the tab attached to `tty` to the front.
+  /// The script evaluates to `found` when it finds the tab.
+  fn focus_script(self, tty: &str) -> String {
+    let found: &[&str] = match self {
+      MacTerminal::Terminal => &["set selected of t to true", "set index of w to 1", "activate"],
+      MacTerminal::ITerm => &["select w", "tell t to select", "tell s to select", "activate"],
+    };
+    self.tab_script(tty, found)
+  }
+
+  /// Builds the AppleScript that runs `command` in the tab attached to `tty`,
+  /// as if it had been typed there. The script evaluates to `found` when it
+  /// finds the tab.
+  fn send_script(self, tty: &str, command: &str) -> String {
+    let command = escape_for_applescript(command);
+    let line = match self {
+      MacTerminal::Terminal => format!("do script \"{}\" in t", command),
+      MacTerminal::ITerm => format!("tell s to write text \"{}\"", command),
+    };
+    self.tab_script(tty, &[&line])
+  }
+
+  /// Looks through every tab (every session, in iTerm) for the one attached
+  /// to `tty`, and runs `found` on it with the window as `w`, the tab as `t`
+  /// and, in iTerm, the session as `s`.
+  fn tab_script(self, tty: &str, found: &[&str]) -> String {
+    let tty = escape_for_applescript(tty);
+    let (mut lines, indent, end) = match self {
+      MacTerminal::Terminal => (
+        vec![
+          "tell application \"Terminal\"".to_string(),
+          "  repeat with w in windows".to_string(),
+          "    repeat with t in tabs of w".to_string(),
+          format!("      if tty of t is \"{}\" then", tty),
+        ],
+        "        ",
+        &["      end if", "    end repeat", "  end repeat", "end tell"][..],
+      ),
+      MacTerminal::ITerm => (
+        vec![
+          "tell application \"iTerm\"".to_string(),
+          "  repeat with w in windows".to_string(),
+          "    repeat with t in tabs of w".to_string(),
+          "      repeat with s in sessions of t".to_string(),
+          format!("        if tty of s is \"{}\" then", tty),
+        ],
+        "          ",
+        &["        end if", "      end repeat", "    end repeat", "  end repeat", "end tell"][..],
+      ),
+    };
+    lines.extend(found.iter().chain(&["return \"found\""]).map(|line| format!("{}{}", indent, line)));
+    lines.extend(end.iter().map(|line| line.to_string()));
+    lines.join("\n")
+  }
+}
+
+/// Looks for `program` in the directories listed in `PATH`.
+///
+/// On Windows a missing extension is filled in from `PATHEXT` (`.exe` first
+/// when it isn't set), matching how `cmd` resolves bare program names, so
+/// npm shims such as `claude.cmd` are found too.
+fn find_on_path(program: &str) -> Option<std::path::PathBuf> {
+  let path = std::env::var_os("PATH")?;
+  let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string());
+  std::env::split_paths(&path).find_map(|dir| {
+    let candidate = dir.join(program);
+    if candidate.is_file() {
+      return Some(candidate);
+    }
+    if cfg!(target_os = "windows") && candidate.extension().is_none() {
+      return extensions
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| candidate.with_extension(ext.trim_start_matches('.')))
+        .find(|exe| exe.is_file());
+    }
+    None
+  })
+}
+
+/// Rejects a command or argument with a line break in it, which the shell
+/// would take as the end of one command and the start of the next. With
+/// `escape_newlines` each line break becomes a space instead, so what
+/// follows it is only ever more arguments.
+///
+/// The options that end up in the same shell line are held to the same
+/// rule, failing with `MultilineOption`: the title, `on_complete`, the extra
+/// terminal arguments, the SSH user and remote directory, and the name of
+/// the environment to activate.
+fn guard_newlines(mut spec: AgentSpec) -> Result<AgentSpec, AgentError> {
+  let escape = spec.escape_newlines.unwrap_or(false);
+  let guard = |text: &mut String, error: &dyn Fn() -> AgentError| {
+    if text.contains(['\n', '\r']) {
+      if !escape {
+        return Err(error());
+      }
+      *text = text.replace("\r\n", " ").replace(['\n', '\r'], " ");
+    }
+    Ok(())
+  };
+  let option = |name: &'static str| move || AgentError::MultilineOption(name.to_string());
+
+  guard(&mut spec.command, &|| AgentError::MultilineCommand)?;
+  for arg in spec.args.iter_mut().flatten() {
+    guard(arg, &|| AgentError::MultilineCommand)?;
+  }
+  if let Some(title) = &mut spec.title {
+    guard(title, &option("title"))?;
+  }
+  if let Some(OnComplete::Interactive(Some(command)) | OnComplete::Run(command)) = &mut spec.on_complete {
+    guard(command, &option("on_complete"))?;
+  }
+  for arg in spec.extra_terminal_args.iter_mut().flatten() {
+    guard(arg, &option("extra_terminal_args"))?;
+  }
+  if let Some(user) = &mut spec.ssh_user {
+    guard(user, &option("ssh_user"))?;
+  }
+  if let Some(dir) = &mut spec.remote_working_directory {
+    guard(dir, &option("remote_working_directory"))?;
+  }
+  if let Some(ActivationSpec::Venv(name) | ActivationSpec::Conda(name) | ActivationSpec::Nix(Some(name))) =
+    &mut spec.activate
   {
-    // Add delay mechanism to Claude command to allow terminal to initialize
-    // This creates a command that waits 2 seconds before executing Claude
-    let command_with_delay = format!("sleep 2 && {}", command);
-    
-    let status = Command::new("osascript")
-      .args(&["-e", &format!("tell application \"Terminal\" to do script \"{}\"", command_with_delay)])
-      .status()
-      .map_err(|e| e.to_string())?;
-    
-    if status.success() {
-      Ok("Terminal launched successfully".to_string())
-    } else {
-      Err("Failed to launch terminal".to_string())
+    guard(name, &option("activate"))?;
+  }
+  Ok(spec)
+}
+
+/// Rejects extra terminal flags that repeat the command, which would run it
+/// twice or leave the terminal trying to run it as an option.
+fn validate_extra_terminal_args(spec: &AgentSpec) -> Result<(), AgentError> {
+  let program = command_program(spec);
+  let repeated = spec.extra_terminal_args().iter().find(|arg| {
+    let arg = arg.trim();
+    arg == spec.command.trim() || program.as_deref() == Some(arg)
+  });
+  match repeated {
+    Some(arg) => Err(AgentError::InvalidTerminalArgs(arg.clone())),
+    None => Ok(()),
+  }
+}
+
+/// Shell builtins and keywords that can start a command line but are never
+/// found on `PATH`, so the pre-flight check lets them through.
+const SHELL_BUILTINS: &[&str] = &[
+  ".", "[", "alias", "case", "cd", "echo", "eval", "exec", "export", "for", "if", "printf", "set",
+  "source", "test", "time", "unset", "until", "while", "call", "cls", "popd", "pushd", "start", "title",
+];
+
+/// Works out which program `spec` will run, for the pre-flight check.
+///
+/// For a command line this is its first word after any `NAME=value`
+/// assignments. `None` means there is nothing worth checking: a builtin, or a
+/// word whose quoting we'd have to fully parse to know what it names.
+fn command_program(spec: &AgentSpec) -> Option<String> {
+  if spec.args.is_some() {
+    return Some(spec.command.clone());
+  }
+
+  let word = spec.command.split_whitespace().find(|word| !word.contains('='))?;
+  let unquoted = ['\'', '"']
+    .into_iter()
+    .find_map(|quote| word.strip_prefix(quote).and_then(|w| w.strip_suffix(quote)))
+    .unwrap_or(word);
+  let shell_syntax = |c: char| "'\"`$()<>|&;*?".contains(c);
+  if unquoted.is_empty() || unquoted.contains(shell_syntax) || SHELL_BUILTINS.contains(&unquoted) {
+    None
+  } else {
+    Some(unquoted.to_string())
+  }
+}
+
+/// Returns whether `program` can be run from `working_directory`.
+///
+/// Apps started from the macOS Dock or a desktop launcher often have a much
+/// shorter `PATH` than the user's terminal, so a program we can't see is
+/// looked up once more through the user's login shell before giving up.
+fn program_exists(program: &str, working_directory: Option<&str>, timeout_ms: u64) -> bool {
+  if program.contains('/') || (cfg!(target_os = "windows") && program.contains('\\')) {
+    let path = Path::new(program);
+    return match working_directory {
+      Some(dir) if path.is_relative() => Path::new(dir).join(path).is_file(),
+      _ => path.is_file(),
+    };
+  }
+  if find_on_path(program).is_some() {
+    return true;
+  }
+
+  #[cfg(unix)]
+  {
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let lookup = format!("command -v {}", shell_quote(program));
+    output_with_timeout(Command::new(shell).args(["-lc", &lookup]), timeout_ms)
+      .is_ok_and(|output| output.status.success())
+  }
+  #[cfg(not(unix))]
+  {
+    let _ = timeout_ms;
+    false
+  }
+}
+
+/// Console hosts that can run an agent on Windows.
+#[cfg(any(target_os = "windows", test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WindowsTerminal {
+  /// Windows Terminal (`wt.exe`), opening the command in a new tab.
+  WindowsTerminal,
+  /// The classic console via `cmd /C start cmd /k`, or a `cmd /k` of its own
+  /// with `direct_console`.
+  Cmd,
+}
+
+#[cfg(any(target_os = "windows", test))]
+impl WindowsTerminal {
+  fn from_name(name: &str) -> Option<Self> {
+    match name.trim().to_ascii_lowercase().as_str() {
+      "wt" | "wt.exe" | "windows-terminal" | "windows terminal" => Some(WindowsTerminal::WindowsTerminal),
+      "cmd" | "cmd.exe" => Some(WindowsTerminal::Cmd),
+      _ => None,
     }
   }
 
+  /// cmd ships with Windows; Windows Terminal has to be on PATH.
   #[cfg(target_os = "windows")]
-  {
-    // Add delay mechanism to Claude command to allow terminal to initialize
-    let command_with_delay = format!("timeout /t 2 && {}", command);
-    
-    let status = Command::new("cmd")
-      .args(&["/C", "start", "cmd", "/k", &command_with_delay])
-      .status()
-      .map_err(|e| e.to_string())?;
-    
-    if status.success() {
-      Ok("Terminal launched successfully".to_string())
-    } else {
-      Err("Failed to launch terminal".to_string())
+  fn is_installed(self) -> bool {
+    match self {
+      WindowsTerminal::WindowsTerminal => find_on_path("wt.exe").is_some(),
+      WindowsTerminal::Cmd => true,
     }
   }
 
-  #[cfg(target_os = "linux")]
-  {
-    // Add delay mechanism to Claude command to allow terminal to initialize
-    let command_with_delay = format!("sleep 2 && {};bash", command);
-    
-    // Try common Linux terminals
-    let terminals = vec![
-      ("gnome-terminal", vec!["--", "bash", "-c", &command_with_delay]),
-      ("xterm", vec!["-e", &command_with_delay]),
-      ("konsole", vec!["--noclose", "-e", &command_with_delay])
-    ];
+  /// Whether `spec` runs its shell as the console's own process rather than
+  /// through `start`, which only the classic console can.
+  fn spawns_directly(self, spec: &AgentSpec) -> bool {
+    self == WindowsTerminal::Cmd && spec.direct_console.unwrap_or(false)
+  }
 
-    for (terminal, args) in terminals {
-      if let Ok(status) = Command::new(terminal).args(args).status() {
-        if status.success() {
-          return Ok("Terminal launched successfully".to_string());
+  /// Returns the program and arguments that open a console running `inner`.
+  ///
+  /// `wt` treats a bare `;` as a separator between its own subcommands, so
+  /// semicolons in the command are escaped as `\;`. It also ignores the cwd of
+  /// the process that started it, so the directory is passed with `-d`.
+  ///
+  /// Only `wt` takes a title here; classic consoles get theirs from the `title`
+  /// builtin (see `cmd_title_prefix`) since `start` would need raw quoting.
+  ///
+  /// `extra` goes before `new-tab` for `wt`, where its window options are
+  /// accepted, and straight after `start` for cmd (`/max`, `/min`, ...).
+  fn launch_args(
+    self,
+    inner: &[String],
+    working_directory: Option<&str>,
+    title: Option<&str>,
+    profile: Option<&str>,
+    reuse_window: bool,
+    extra: &[String],
+  ) -> (&'static str, Vec<String>) {
+    match self {
+      WindowsTerminal::WindowsTerminal => {
+        // `-w 0` is the most recently used window, which wt creates if there
+        // isn't one; classic consoles have no tabs to reuse
+        let mut args = if reuse_window { vec!["-w".to_string(), "0".to_string()] } else { Vec::new() };
+        args.extend(extra.iter().cloned());
+        args.push("new-tab".to_string());
+        if let Some(title) = title {
+          args.extend(["--title".to_string(), title.to_string()]);
         }
+        if let Some(profile) = profile {
+          args.extend(["--profile".to_string(), profile.to_string()]);
+        }
+        if let Some(dir) = working_directory {
+          args.extend(["-d".to_string(), dir.to_string()]);
+        }
+        args.extend(inner.iter().map(|arg| arg.replace(';', "\\;")));
+        ("wt", args)
+      }
+      WindowsTerminal::Cmd => {
+        let mut args = vec!["/C".to_string(), "start".to_string()];
+        args.extend(extra.iter().cloned());
+        args.extend(inner.iter().cloned());
+        ("cmd", args)
       }
     }
-    
-    Err("Failed to launch terminal on Linux".to_string())
   }
 }
 
-fn main() {
-  tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![launch_agent_terminal])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-}
\ No newline at end of file
+/// Returns a cmd `title` builtin invocation that names the console window.
+#[cfg(any(target_os = "windows", test))]
+fn cmd_title_prefix(title: Option<&str>) -> String {
+  match title {
+    Some(title) => {
+      let mut escaped = String::with_capacity(title.len());
+      for c in title.chars() {
+        if matches!(c, '^' | '&' | '|' | '<' | '>' | '(' | ')') {
+          escaped.push('^');
+        }
+        escaped.push(c);
+      }
+      format!("title {} && ", escaped)
+    }
+    None => String::new(),
+  }
+}
+
+/// Quotes `input` as a PowerShell single-quoted string, in which the only
+/// special character is `'` itself, written twice.
+#[cfg(any(target_os = "windows", test))]
+fn ps_quote(input: &str) -> String {
+  format!("'{}'", input.replace('\'', "''"))
+}
+
+/// Writes `arg` as a PowerShell word, quoting it only when needed.
+#[cfg(any(target_os = "windows", test))]
+fn ps_word(arg: &str) -> String {
+  let plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=\\".contains(c));
+  if plain {
+    arg.to_string()
+  } else {
+    ps_quote(arg)
+  }
+}
+
+/// Shells that can run an agent on Windows.
+#[cfg(any(target_os = "windows", test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WindowsShell {
+  Cmd,
+  /// Windows PowerShell 5, which ships with Windows.
+  PowerShell,
+  /// PowerShell 7+, installed separately.
+  Pwsh,
+}
+
+#[cfg(any(target_os = "windows", test))]
+impl WindowsShell {
+  fn from_name(name: &str) -> Option<Self> {
+    match name.trim().to_ascii_lowercase().as_str() {
+      "cmd" | "cmd.exe" => Some(WindowsShell::Cmd),
+      "powershell" | "powershell.exe" => Some(WindowsShell::PowerShell),
+      "pwsh" | "pwsh.exe" => Some(WindowsShell::Pwsh),
+      _ => None,
+    }
+  }
+
+  /// The shell `spec` asks for; cmd unless it names another one.
+  fn for_spec(spec: &AgentSpec) -> Self {
+    spec.shell.as_deref().and_then(WindowsShell::from_name).unwrap_or(WindowsShell::Cmd)
+  }
+
+  fn program(self) -> &'static str {
+    match self {
+      WindowsShell::Cmd => "cmd",
+      WindowsShell::PowerShell => "powershell",
+      WindowsShell::Pwsh => "pwsh",
+    }
+  }
+
+  /// Returns the shell invocation that runs `spec.command` after the startup
+  /// delay and then does what its `on_complete` says.
+  fn args(self, spec: &AgentSpec) -> Vec<String> {
+    let title = spec.title.as_deref();
+    let delay_ms = spec.startup_delay_ms();
+    // Staying at this shell needs no follow-up, just a shell that doesn't exit
+    let (stay_open, follow_up) = match spec.on_complete() {
+      OnComplete::Interactive(None) => (true, None),
+      OnComplete::Interactive(Some(shell)) => (false, Some(shell)),
+      OnComplete::Close => (false, None),
+      OnComplete::Run(command) => (false, Some(command)),
+    };
+    match self {
+      WindowsShell::Cmd => {
+        // Name the console, wait out the startup delay, then run the command
+        let mut command = format!(
+          "{}{}{}",
+          cmd_title_prefix(title),
+          cmd_delay_prefix(delay_ms),
+          cmd_tee(&spec.command_line(cmd_word), spec.log_path.as_deref())
+        );
+        if let Some(follow_up) = follow_up {
+          command = format!("{} & {}", command, follow_up);
+        }
+        // /k leaves the prompt open afterwards, /c closes the console
+        let hold = if stay_open { "/k" } else { "/c" };
+        vec!["cmd".to_string(), hold.to_string(), command]
+      }
+      WindowsShell::PowerShell | WindowsShell::Pwsh => {
+        let mut script = String::new();
+        if let Some(title) = title {
+          script.push_str(&format!("$Host.UI.RawUI.WindowTitle = {}; ", ps_quote(title)));
+        }
+        if delay_ms > 0 {
+          script.push_str(&format!("Start-Sleep -Milliseconds {}; ", delay_ms));
+        }
+        // A quoted program name is just a string to PowerShell unless it is
+        // invoked with the call operator
+        let command = match spec.args {
+          Some(_) => format!("& {}", spec.command_line(ps_word)),
+          None => spec.command.clone(),
+        };
+        script.push_str(&ps_tee(&command, spec.log_path.as_deref()));
+        if let Some(follow_up) = follow_up {
+          script.push_str(&format!("; {}", follow_up));
+        }
+        let mut args = vec![self.program().to_string()];
+        if stay_open {
+          args.push("-NoExit".to_string());
+        }
+        args.extend(["-Command".to_string(), script]);
+        args
+      }
+    }
+  }
+}
+
+/// Translates a Windows path such as `C:\Users\me` into the `/mnt/c/Users/me`
+/// form WSL mounts it under. Paths that are already Linux-style are kept as is.
+#[cfg(any(target_os = "windows", test))]
+fn windows_path_to_wsl(path: &str) -> String {
+  let bytes = path.as_bytes();
+  if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+    let drive = (bytes[0] as char).to_ascii_lowercase();
+    let rest = path[2..].replace('\\', "/");
+    format!("/mnt/{}/{}", drive, rest.trim_start_matches('/'))
+  } else {
+    path.replace('\\', "/")
+  }
+}
+
+/// Builds the `wsl.exe` invocation that runs `command` in a login bash shell,
+/// then `follow_up`, like the Linux launcher does: `bash` to leave an
+/// interactive shell open, or another command.
+#[cfg(any(target_os = "windows", test))]
+fn wsl_args(
+  distro: Option<&str>,
+  working_directory: Option<&str>,
+  startup_delay_ms: u64,
+  command: &str,
+  follow_up: Option<&str>,
+) -> Vec<String> {
+  let hold = follow_up.map(|follow_up| format!("; {}", follow_up)).unwrap_or_default();
+  let mut script = format!("{}{}{}", posix_delay_prefix(startup_delay_ms), command, hold);
+  if let Some(dir) = working_directory {
+    script = format!("cd {} && {}", shell_quote(&windows_path_to_wsl(dir)), script);
+  }
+
+  let mut args = vec!["wsl.exe".to_string()];
+  if let Some(distro) = distro {
+    args.extend(["-d".to_string(), distro.to_string()]);
+  }
+  args.extend(["--".to_string(), "bash".to_string(), "-lc".to_string(), script]);
+  args
+}
+
+/// Terminal emulators tried on Linux, in order, when neither the caller nor
+/// `$TERMINAL` names one that can be started.
+#[cfg(any(target_os = "linux", test))]
+const LINUX_TERMINALS: &[&str] = &[
+  "gnome-terminal",
+  "xterm",
+  "konsole",
+  "alacritty",
+  "kitty",
+  "wezterm",
+  "terminator",
+  "tilix",
+  "ghostty",
+];
+
+/// Orders the terminals to try: the caller's choice, then their preference
+/// list, then the ones the `desktop` points to, then the `builtin` list,
+/// skipping duplicates. Each comes with where it was found.
+#[cfg(any(target_os = "linux", test))]
+fn linux_terminal_candidates(
+  requested: Option<&str>,
+  preference: &[String],
+  desktop: &DesktopTerminals,
+  builtin: &[&str],
+) -> Vec<(String, TerminalSource)> {
+  let mut candidates: Vec<(String, TerminalSource)> = Vec::new();
+  let chosen = requested
+    .map(|terminal| (terminal, TerminalSource::Requested))
+    .into_iter()
+    .chain(preference.iter().map(|terminal| (terminal.as_str(), TerminalSource::Preference)))
+    .chain(desktop.candidates())
+    .chain(builtin.iter().map(|terminal| (*terminal, TerminalSource::Builtin)));
+  for (terminal, source) in chosen {
+    let terminal = terminal.trim();
+    if !terminal.is_empty() && !candidates.iter().any(|(c, _)| c == terminal) {
+      candidates.push((terminal.to_string(), source));
+    }
+  }
+  candidates
+}
+
+/// The terminals the user's own setup points to on Linux, in the order they
+/// are tried after the caller's choices.
+#[cfg(any(target_os = "linux", test))]
+#[derive(Debug, Default)]
+struct DesktopTerminals {
+  /// `$TERMINAL`.
+  env: Option<String>,
+  /// `xdg-terminal-exec` when it is installed, which opens whichever
+  /// terminal is set as the default under the XDG default terminal spec.
+  xdg: Option<String>,
+  /// The default in GNOME's settings, or else the one Debian and Ubuntu's
+  /// `x-terminal-emulator` points to.
+  system_default: Option<String>,
+}
+
+#[cfg(any(target_os = "linux", test))]
+impl DesktopTerminals {
+  /// Reads `$TERMINAL` and asks the desktop for its default. Anything that
+  /// isn't set up, or can't be asked, is left out.
+  #[cfg(target_os = "linux")]
+  fn detect() -> Self {
+    let gnome = || {
+      let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+      let gnome = desktop.split(':').any(|name| name.eq_ignore_ascii_case("gnome"));
+      if !gnome || find_on_path("gsettings").is_none() {
+        return None;
+      }
+      let mut gsettings = Command::new("gsettings");
+      gsettings.args(["get", "org.gnome.desktop.default-applications.terminal", "exec"]);
+      let output = output_with_timeout(&mut gsettings, DEFAULT_LAUNCH_TIMEOUT_MS).ok()?;
+      output.status.success().then(|| parse_gsettings_program(&String::from_utf8_lossy(&output.stdout)))?
+    };
+    let debian = || find_on_path("x-terminal-emulator").map(|path| alternative_terminal(&path));
+    DesktopTerminals {
+      env: std::env::var("TERMINAL").ok(),
+      xdg: find_on_path("xdg-terminal-exec").map(|_| "xdg-terminal-exec".to_string()),
+      system_default: gnome().or_else(debian),
+    }
+  }
+
+  /// `$TERMINAL`, then `xdg-terminal-exec`, then the system default, for the
+  /// ones there are.
+  fn candidates(&self) -> impl Iterator<Item = (&str, TerminalSource)> {
+    [
+      (&self.env, TerminalSource::Env),
+      (&self.xdg, TerminalSource::Xdg),
+      (&self.system_default, TerminalSource::SystemDefault),
+    ]
+    .into_iter()
+    .filter_map(|(terminal, source)| Some((terminal.as_deref()?, source)))
+  }
+}
+
+/// Returns the program in `gsettings get` output for a string key, which is
+/// printed quoted (`'gnome-terminal'`).
+#[cfg(any(target_os = "linux", test))]
+fn parse_gsettings_program(output: &str) -> Option<String> {
+  let value = output.trim().trim_matches(|c| c == '\'' || c == '"');
+  value.split_whitespace().next().map(str::to_string)
+}
+
+/// The terminal the `x-terminal-emulator` alternative at `path` links to, by
+/// name when it is one of `LINUX_TERMINALS` so it gets its own flags (Debian
+/// links gnome-terminal through `gnome-terminal.wrapper`). Anything else is
+/// started as `x-terminal-emulator`, which Debian requires to take `-e`.
+#[cfg(any(target_os = "linux", test))]
+fn alternative_terminal(path: &Path) -> String {
+  let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+  let name = target.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+  let name = name.strip_suffix(".wrapper").unwrap_or(name);
+  match LINUX_TERMINALS.iter().find(|known| **known == name) {
+    Some(known) => known.to_string(),
+    None => "x-terminal-emulator".to_string(),
+  }
+}
+
+/// Flatpak application ids of the terminals in `LINUX_TERMINALS` that are
+/// published as flatpaks.
+#[cfg(any(target_os = "linux", test))]
+const FLATPAK_TERMINALS: &[(&str, &str)] = &[
+  ("gnome-terminal", "org.gnome.Terminal"),
+  ("konsole", "org.kde.konsole"),
+  ("wezterm", "org.wezfurlong.wezterm"),
+  ("tilix", "com.gexperts.Tilix"),
+  ("ghostty", "com.mitchellh.ghostty"),
+];
+
+/// Terminals installed as snaps or flatpaks. Snap puts its wrappers in
+/// `/snap/bin`, which apps started from the desktop don't always have on
+/// `PATH`, and flatpaks aren't on `PATH` at all.
+#[cfg(any(target_os = "linux", test))]
+#[derive(Debug, Default)]
+struct PackagedTerminals {
+  snaps: Vec<String>,
+  flatpaks: Vec<String>,
+}
+
+#[cfg(any(target_os = "linux", test))]
+impl PackagedTerminals {
+  /// Asks `snap list` and `flatpak list` what is installed. Either tool
+  /// being missing or failing just leaves its list empty.
+  #[cfg(target_os = "linux")]
+  fn detect() -> Self {
+    let listing = |program: &str, args: &[&str]| -> String {
+      if find_on_path(program).is_none() {
+        return String::new();
+      }
+      match output_with_timeout(Command::new(program).args(args), DEFAULT_LAUNCH_TIMEOUT_MS) {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+        _ => String::new(),
+      }
+    };
+    PackagedTerminals {
+      snaps: parse_snap_list(&listing("snap", &["list"])),
+      flatpaks: listing("flatpak", &["list", "--app", "--columns=application"])
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|app_id| !app_id.is_empty())
+        .collect(),
+    }
+  }
+
+  /// How to start `terminal` from its snap or flatpak, if it has one. A snap
+  /// is preferred, since it runs outside any sandbox.
+  fn launcher(&self, terminal: &str) -> Option<Box<dyn TerminalLauncher>> {
+    if self.snaps.iter().any(|snap| snap == terminal) {
+      return Some(Box::new(LinuxTerminal { program: format!("/snap/bin/{}", terminal) }));
+    }
+    let (_, app_id) = FLATPAK_TERMINALS.iter().find(|(name, _)| *name == terminal)?;
+    self.flatpaks.iter().any(|installed| installed == app_id).then(|| {
+      Box::new(FlatpakTerminal { terminal: terminal.to_string(), app_id: app_id.to_string() })
+        as Box<dyn TerminalLauncher>
+    })
+  }
+}
+
+/// Returns the snap names in `snap list` output, whose first column is the
+/// name under a header row.
+#[cfg(any(target_os = "linux", test))]
+fn parse_snap_list(listing: &str) -> Vec<String> {
+  listing.lines().skip(1).filter_map(|line| line.split_whitespace().next()).map(str::to_string).collect()
+}
+
+/// The installed terminals `spec` would be opened in on Linux, in the order
+/// they are tried.
+#[cfg(any(target_os = "linux", test))]
+fn linux_launchers(
+  spec: &AgentSpec,
+  desktop: &DesktopTerminals,
+  builtin: &[&str],
+  packaged: &PackagedTerminals,
+) -> Result<Vec<FoundTerminal>, AgentError> {
+  let candidates =
+    linux_terminal_candidates(spec.terminal.as_deref(), spec.terminal_preference(), desktop, builtin);
+  installed_terminals(candidates, packaged)
+}
+
+/// Keeps the `candidates` that are on `PATH` (or are existing paths), or
+/// else installed in `packaged`, so a terminal that isn't installed is told
+/// apart from one that failed to open. Fails with `NoTerminalFound` listing
+/// every candidate when none are.
+#[cfg(any(target_os = "linux", test))]
+fn installed_terminals(
+  candidates: Vec<(String, TerminalSource)>,
+  packaged: &PackagedTerminals,
+) -> Result<Vec<FoundTerminal>, AgentError> {
+  let mut installed: Vec<FoundTerminal> = Vec::new();
+  let mut missing = Vec::new();
+  for (program, source) in candidates {
+    if find_on_path(&program).is_some() {
+      installed.push((Box::new(LinuxTerminal { program }), source));
+    } else if let Some(launcher) = packaged.launcher(&program) {
+      installed.push((launcher, source));
+    } else {
+      missing.push(program);
+    }
+  }
+  if installed.is_empty() {
+    return Err(AgentError::NoTerminalFound(missing));
+  }
+  Ok(installed)
+}
+
+/// Returns the shell and flag that run a generated script for someone whose
+/// shell is `shell`.
+///
+/// bash and zsh get `-ic` so aliases and `PATH` changes from their rc files
+/// apply, or `-lc` for a `login` shell, which only reads the profile. The
+/// scripts use POSIX syntax, so shells that don't speak it (fish, nushell,
+/// ...) have the script run by `/bin/sh` instead; their own shell is still
+/// the one left open afterwards.
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+fn posix_runner(shell: &str, login: bool) -> [&str; 2] {
+  let name = Path::new(shell).file_name().and_then(|n| n.to_str()).unwrap_or(shell);
+  match name {
+    "bash" | "zsh" if login => [shell, "-lc"],
+    "bash" | "zsh" => [shell, "-ic"],
+    "sh" | "dash" | "ash" | "ksh" | "mksh" => [shell, "-c"],
+    _ => ["/bin/sh", "-c"],
+  }
+}
+
+/// Returns the arguments that make `terminal` run `script` through `runner`
+/// (a shell and its `-c` flag, see `posix_runner`), with `argv` as the
+/// script's positional parameters (`$0`, `$1`, ...).
+///
+/// `terminal` may be a bare name or a full path (as `$TERMINAL` often is).
+/// Emulators we don't know are assumed to follow the common `-e` convention.
+/// `title` is dropped for terminals without a title flag (wezterm, unknown).
+/// `xdg-terminal-exec` stands in for whichever terminal it opens.
+/// `new_tab` asks for a tab in an existing window, which only gnome-terminal,
+/// konsole and terminator can do from the command line; each opens a window
+/// instead when none is running. `profile` is likewise only understood by
+/// gnome-terminal, konsole and tilix.
+///
+/// `extra` goes after those flags and just before the command: ahead of
+/// `--wait --` for gnome-terminal, `-e` for most emulators and tilix, `-x`
+/// for terminator, `start` for wezterm, and the shell itself for kitty.
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+#[allow(clippy::too_many_arguments)]
+fn linux_terminal_args(
+  terminal: &str,
+  title: Option<&str>,
+  profile: Option<&str>,
+  new_tab: bool,
+  extra: &[String],
+  runner: [&str; 2],
+  script: &str,
+  argv: &[String],
+) -> Vec<String> {
+  let name = Path::new(terminal).file_name().and_then(|n| n.to_str()).unwrap_or(terminal);
+
+  let mut args: Vec<String> = Vec::new();
+  if let Some(profile) = profile {
+    match name {
+      "gnome-terminal" | "tilix" => args.push(format!("--profile={}", profile)),
+      "konsole" => args.extend(["--profile".to_string(), profile.to_string()]),
+      _ => {}
+    }
+  }
+  if new_tab {
+    match name {
+      "gnome-terminal" => args.push("--tab".to_string()),
+      "konsole" | "terminator" => args.push("--new-tab".to_string()),
+      _ => {}
+    }
+  }
+  if let Some(title) = title {
+    match name {
+      "gnome-terminal" | "alacritty" | "kitty" => args.extend(["--title".to_string(), title.to_string()]),
+      "xterm" | "terminator" => args.extend(["-T".to_string(), title.to_string()]),
+      "tilix" => args.extend(["-t".to_string(), title.to_string()]),
+      "ghostty" | "xdg-terminal-exec" => args.push(format!("--title={}", title)),
+      "konsole" => args.extend(["-p".to_string(), format!("tabtitle={}", title)]),
+      _ => {}
+    }
+  }
+  args.extend(extra.iter().cloned());
+
+  let prefix: &[&str] = match name {
+    // Without --wait the client exits as soon as the server opens the window,
+    // leaving no process to track
+    "gnome-terminal" => &["--wait", "--"],
+    "konsole" => &["--noclose", "-e"],
+    "kitty" => &[],
+    "wezterm" => &["start", "--"],
+    "terminator" => &["-x"],
+    "xdg-terminal-exec" => &["--"],
+    // tilix takes the whole command line as a single `-e` argument
+    "tilix" => {
+      let mut line = format!("{} {} {}", shell_word(runner[0]), runner[1], shell_quote(script));
+      for arg in argv {
+        line = format!("{} {}", line, shell_quote(arg));
+      }
+      args.extend(["-e".to_string(), line]);
+      return args;
+    }
+    _ => &["-e"],
+  };
+  args.extend(prefix.iter().map(|arg| arg.to_string()));
+  args.extend(runner.iter().map(|arg| arg.to_string()));
+  args.push(script.to_string());
+  args.extend(argv.iter().cloned());
+  args
+}
+
+/// Spawns `command` and returns as soon as the process has started.
+///
+/// Terminal emulators such as `xterm -e` only exit once their window closes, so
+/// waiting on them would hold the invoke open for the whole agent session. The
+/// returned child should be passed to `watch_agent`, which reaps it on exit.
+fn spawn_detached(command: &mut Command) -> std::io::Result<Child> {
+  // Put the child in its own process group so kill_agent_terminal can signal
+  // everything it starts without touching the app itself.
+  #[cfg(unix)]
+  std::os::unix::process::CommandExt::process_group(command, 0);
+
+  // The launchers (`cmd /C start`, `wt`) only hand off to a window they open
+  // themselves, and get to bring it to the front because the app they were
+  // started from is in the foreground. Their own console stays hidden, and
+  // being separate from the app's means the agents outlive a dev console.
+  #[cfg(windows)]
+  hidden_console(command);
+
+  command.spawn()
+}
+
+/// Starts console programs run from the app (taskkill, PowerShell, the
+/// launchers) without a console window of their own flashing up.
+#[cfg(windows)]
+fn hidden_console(command: &mut Command) -> &mut Command {
+  use std::os::windows::process::CommandExt;
+  const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+  command.creation_flags(CREATE_NO_WINDOW)
+}
+
+/// Spawns `command` in a console window of its own, for a shell run without
+/// `start` in between. The app has no standard handles to pass on outside
+/// dev builds, so the shell's are the new console's.
+#[cfg(any(target_os = "windows", test))]
+fn spawn_in_new_console(command: &mut Command) -> std::io::Result<Child> {
+  #[cfg(windows)]
+  {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NEW_CONSOLE: u32 = 0x0000_0010;
+    command.creation_flags(CREATE_NEW_CONSOLE);
+  }
+  command.spawn()
+}
+
+/// How long a freshly spawned terminal is watched for failing straight away.
+const EARLY_EXIT_WINDOW: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Spawns `command` detached, then gives it a moment to fail. A terminal that
+/// exits unsuccessfully in that time (no display, a bad flag) is reported with
+/// what it printed on stderr; one that is still running or exited cleanly, as
+/// `wt` and `cmd /C start` do, is returned.
+///
+/// stderr goes to a temporary file rather than a pipe, so a terminal that keeps
+/// printing warnings after we stop looking neither blocks nor gets SIGPIPE.
+fn spawn_checked(command: &mut Command, program: &str) -> Result<Child, AgentError> {
+  static NEXT_CAPTURE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+  let seq = NEXT_CAPTURE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  let path = std::env::temp_dir().join(format!("agentic-sync-{}-{}.stderr", std::process::id(), seq));
+  let Ok(capture) = std::fs::File::create(&path) else {
+    return spawn_detached(command).map_err(|e| AgentError::SpawnFailed(e.to_string()));
+  };
+  command.stderr(capture);
+  let spawned = spawn_detached(command);
+  let read_and_remove = || {
+    let stderr = std::fs::read(&path).unwrap_or_default();
+    remove_capture(path.clone());
+    stderr
+  };
+  let mut child = match spawned {
+    Ok(child) => child,
+    Err(e) => {
+      read_and_remove();
+      return Err(AgentError::SpawnFailed(e.to_string()));
+    }
+  };
+
+  let deadline = std::time::Instant::now() + EARLY_EXIT_WINDOW;
+  let status = loop {
+    match child.try_wait() {
+      Ok(None) if std::time::Instant::now() < deadline => thread::sleep(std::time::Duration::from_millis(20)),
+      Ok(status) => break status,
+      Err(_) => break None,
+    }
+  };
+  let stderr = read_and_remove();
+  debug!("{} started as pid {}, status after {:?}: {:?}", program, child.id(), EARLY_EXIT_WINDOW, status);
+  match status {
+    Some(status) if !status.success() => {
+      let output = std::process::Output { status, stdout: Vec::new(), stderr };
+      Err(AgentError::LaunchFailed(exit_failure(program, &output)))
+    }
+    _ => Ok(child),
+  }
+}
+
+/// How often `remove_capture` retries a file that is still open.
+const CAPTURE_REMOVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Deletes the file `spawn_checked` captured stderr in. Windows won't delete a file
+/// that is still open, and a terminal that is still running (or something it
+/// started) holds this one, so there it is retried in the background until
+/// they let go of it.
+fn remove_capture(path: std::path::PathBuf) {
+  if std::fs::remove_file(&path).is_ok() || !path.exists() {
+    return;
+  }
+  thread::spawn(move || {
+    while std::fs::remove_file(&path).is_err() && path.exists() {
+      thread::sleep(CAPTURE_REMOVE_INTERVAL);
+    }
+  });
+}
+
+/// Describes a launcher that exited unsuccessfully, including whatever it
+/// printed on stderr.
+fn exit_failure(program: &str, output: &std::process::Output) -> String {
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  match stderr.trim() {
+    "" => format!("{} exited with {}", program, output.status),
+    stderr => format!("{} exited with {}: {}", program, output.status, stderr),
+  }
+}
+
+/// How long launchers that we wait on get by default. Generous because macOS
+/// holds osascript open while it asks for automation permission.
+const DEFAULT_LAUNCH_TIMEOUT_MS: u64 = 60_000;
+
+/// Retries a terminal that failed to open gets by default, enough to ride out
+/// a display that isn't ready yet right after login.
+const DEFAULT_MAX_RETRIES: u32 = 1;
+const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+
+/// How soon after a launch the same command may be launched again in the same
+/// directory by default; about the time between the clicks of a double-click.
+const DEFAULT_DEDUPE_WINDOW_MS: u64 = 1000;
+
+/// Longest command line, in bytes, accepted by default. Linux won't pass a
+/// single argument over 128 KiB to a program, and the terminal adds to it.
+const DEFAULT_MAX_COMMAND_LENGTH: usize = 100_000;
+
+/// Command lines longer than this are run from a script file on macOS and
+/// Windows; cmd takes at most 8191 characters, osascript's source and the
+/// launchers' own wrapping included.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const SCRIPT_FILE_THRESHOLD: usize = 4096;
+
+/// Waits for `child` to exit and collects its output, killing it and returning
+/// `AgentError::Timeout` if it takes longer than `timeout_ms`.
+///
+/// Only used for launchers that exit once the terminal is open (osascript,
+/// tmux); their output is small enough that it can't fill the pipes while we
+/// poll.
+fn wait_with_timeout(mut child: Child, timeout_ms: u64) -> Result<std::process::Output, AgentError> {
+  let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+  loop {
+    match child.try_wait() {
+      Ok(Some(_)) => break,
+      Ok(None) if std::time::Instant::now() >= deadline => {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(AgentError::Timeout(timeout_ms));
+      }
+      Ok(None) => thread::sleep(std::time::Duration::from_millis(20)),
+      Err(e) => return Err(AgentError::SpawnFailed(e.to_string())),
+    }
+  }
+  child.wait_with_output().map_err(|e| AgentError::SpawnFailed(e.to_string()))
+}
+
+/// Spawns `command` with its output captured and waits for it as
+/// `wait_with_timeout` does.
+fn output_with_timeout(command: &mut Command, timeout_ms: u64) -> Result<std::process::Output, AgentError> {
+  let child = command
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+  wait_with_timeout(child, timeout_ms)
+}
+
+/// How often an agent that isn't our child is checked for having exited.
+const EXIT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Waits on a background thread for a launched agent to exit, then calls
+/// `on_exit` with its exit code.
+///
+/// When there is a child process this waits on it directly. Note that this is
+/// the terminal emulator (or `wt`/`cmd start` on Windows), so a wrapper that
+/// hands the window off and exits is reported as exited even though the agent
+/// keeps running. macOS tabs have no child of ours at all, so their tty is
+/// polled until nothing is attached to it; anything else (such as a tmux pane)
+/// is polled by PID. No exit code is available in the polled cases.
+fn watch_agent<F>(launched: LaunchedTerminal, on_exit: F)
+where
+  F: FnOnce(Option<i32>) + Send + 'static,
+{
+  thread::spawn(move || match launched {
+    LaunchedTerminal { child: Some(mut child), .. } => {
+      let code = child.wait().ok().and_then(|status| status.code());
+      on_exit(code);
+    }
+    #[cfg(unix)]
+    LaunchedTerminal { tty: Some(tty), .. } => {
+      while tty_in_use(&tty) {
+        thread::sleep(EXIT_POLL_INTERVAL);
+      }
+      on_exit(None);
+    }
+    LaunchedTerminal { pid, .. } => {
+      while process_exists(pid) {
+        thread::sleep(EXIT_POLL_INTERVAL);
+      }
+      on_exit(None);
+    }
+  });
+}
+
+/// Payload of the `agent-output` event: a line a headless agent printed, or
+/// whatever a `pty` agent wrote to its terminal since the last event.
+#[derive(Debug, Clone, serde::Serialize)]
+struct AgentOutput {
+  id: String,
+  /// `stdout` or `stderr` for headless agents, `pty` for terminal output.
+  stream: &'static str,
+  data: String,
+}
+
+/// Emits each line `child` writes to its piped stdout and stderr as an
+/// `agent-output` event, on a thread per stream. Streams that weren't piped
+/// are skipped, so this does nothing for agents running in a terminal.
+fn stream_output(app: &tauri::AppHandle, id: &str, child: &mut Child) -> Vec<thread::JoinHandle<()>> {
+  use std::io::{BufRead, BufReader, Read};
+
+  let streams: [(&'static str, Option<Box<dyn Read + Send>>); 2] = [
+    ("stdout", child.stdout.take().map(|out| Box::new(out) as Box<dyn Read + Send>)),
+    ("stderr", child.stderr.take().map(|err| Box::new(err) as Box<dyn Read + Send>)),
+  ];
+  streams
+    .into_iter()
+    .filter_map(|(stream, reader)| {
+      let reader = reader?;
+      let app = app.clone();
+      let id = id.to_string();
+      Some(thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) {
+          let data = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+          let _ = app.emit_all("agent-output", AgentOutput { id: id.clone(), stream, data });
+          line.clear();
+        }
+      }))
+    })
+    .collect()
+}
+
+/// Payload of the `agent-launching` event, sent as soon as a launch has its
+/// id and before any terminal is opened. `agent-launched` follows with the
+/// agent's `AgentInfo` once it is running; a launch that fails is only
+/// reported through the command's result.
+#[derive(Debug, Clone, serde::Serialize)]
+struct AgentLaunching {
+  id: String,
+  command: String,
+  title: Option<String>,
+}
+
+/// Payload of the `agent-exited` event.
+#[derive(Debug, Clone, serde::Serialize)]
+struct AgentExited {
+  id: String,
+  exit_code: Option<i32>,
+}
+
+/// Information about a terminal launched by `launch_terminal`.
+///
+/// `pid` is the process that was spawned to open the terminal. On macOS this
+/// is the `osascript` process that asked Terminal.app to run the command, not
+/// the Terminal tab itself, and it exits as soon as the script has been sent;
+/// the tab is identified by its `tty` instead and there is no `child`.
+#[derive(Debug)]
+struct LaunchedTerminal {
+  pid: u32,
+  terminal: String,
+  tty: Option<String>,
+  child: Option<Child>,
+}
+
+/// A point on screen in pixels, from the top left of the main display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct WindowPosition {
+  x: i32,
+  y: i32,
+}
+
+/// An environment to run the agent in, serialized as `{ "venv": "/work/.venv" }`,
+/// `{ "conda": "ml" }` or `{ "nix": ".#dev" }` (`{ "nix": null }` for the flake
+/// in the working directory).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ActivationSpec {
+  /// A Python virtual environment directory, relative to the working
+  /// directory unless absolute.
+  Venv(String),
+  /// A conda environment, by name.
+  Conda(String),
+  /// A Nix flake's development shell; not available on Windows outside WSL.
+  Nix(Option<String>),
+}
+
+/// What an agent's window does once the agent exits, serialized as
+/// `{ "interactive": "zsh" }` (`{ "interactive": null }` for the usual shell),
+/// `"close"` or `{ "run": "notify-send done" }`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OnComplete {
+  /// Stay open at this shell, or the launch's `shell` when not given.
+  Interactive(Option<String>),
+  /// End with the agent, which closes the window unless the terminal is set
+  /// to keep windows of finished shells.
+  Close,
+  /// Run this command line, in the agent's shell, then end.
+  Run(String),
+}
+
+impl OnComplete {
+  /// What a POSIX shell runs after the agent, if anything: the interactive
+  /// shell, which is `default_shell` unless another was named, or the
+  /// follow-up command.
+  fn posix_follow_up(&self, default_shell: &str) -> Option<String> {
+    match self {
+      OnComplete::Interactive(shell) => Some(shell_word(shell.as_deref().unwrap_or(default_shell))),
+      OnComplete::Close => None,
+      OnComplete::Run(command) => Some(command.clone()),
+    }
+  }
+}
+
+/// The `rows`, `cols` and `position` of a spec, for terminals that can open
+/// a window at a given size or place. Each terminal ignores what it can't do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WindowGeometry {
+  /// Columns and rows.
+  size: Option<(u16, u16)>,
+  position: Option<WindowPosition>,
+}
+
+impl WindowGeometry {
+  /// The X11 `COLSxROWS+X+Y` form taken by xterm and the GTK terminals.
+  #[cfg(any(target_os = "linux", target_os = "macos", test))]
+  fn x11(self) -> String {
+    let size = self.size.map(|(cols, rows)| format!("{}x{}", cols, rows)).unwrap_or_default();
+    let position = self.position.map(|p| format!("{:+}{:+}", p.x, p.y)).unwrap_or_default();
+    size + &position
+  }
+}
+
+/// Flags that open `terminal` at `geometry`, for the emulators that have
+/// them: xterm, gnome-terminal and terminator.
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+fn linux_geometry_args(terminal: &str, geometry: Option<WindowGeometry>) -> Vec<String> {
+  let name = Path::new(terminal).file_name().and_then(|n| n.to_str()).unwrap_or(terminal);
+  match (name, geometry) {
+    ("xterm", Some(geometry)) => vec!["-geometry".to_string(), geometry.x11()],
+    ("gnome-terminal" | "terminator", Some(geometry)) => vec![format!("--geometry={}", geometry.x11())],
+    _ => Vec::new(),
+  }
+}
+
+/// Windows Terminal's `--size` and `--pos` flags for `geometry`. They apply
+/// to the new window, so they come before `new-tab`.
+#[cfg(any(target_os = "windows", test))]
+fn wt_geometry_args(geometry: Option<WindowGeometry>) -> Vec<String> {
+  let Some(geometry) = geometry else {
+    return Vec::new();
+  };
+  let mut args = Vec::new();
+  if let Some((cols, rows)) = geometry.size {
+    args.extend(["--size".to_string(), format!("{},{}", cols, rows)]);
+  }
+  if let Some(position) = geometry.position {
+    args.extend(["--pos".to_string(), format!("{},{}", position.x, position.y)]);
+  }
+  args
+}
+
+/// Everything needed to launch one agent.
+///
+/// Only `command` is required. Whatever else is left out is taken from the
+/// config, or else keeps the built-in behaviour. This is what
+/// `launch_agent_terminal` and the other launch commands take.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+struct AgentSpec {
+  /// Shell command line to run, or the program when `args` is given.
+  command: String,
+  /// Id to register the agent under, such as a name people will recognise,
+  /// instead of a generated `agent-N`. Refused while another agent with the
+  /// same id is running.
+  #[serde(default)]
+  id: Option<String>,
+  /// Arguments for `command`, passed to it without shell interpretation
+  /// wherever the terminal allows.
+  #[serde(default)]
+  args: Option<Vec<String>>,
+  #[serde(default)]
+  title: Option<String>,
+  #[serde(default)]
+  working_directory: Option<String>,
+  #[serde(default)]
+  terminal: Option<String>,
+  /// Terminals to try in order after `terminal`, ahead of the built-in order.
+  /// Names this platform doesn't know are skipped, so one list can be shared.
+  #[serde(default)]
+  terminal_preference: Option<Vec<String>>,
+  #[serde(default)]
+  wsl: Option<bool>,
+  #[serde(default)]
+  distro: Option<String>,
+  #[serde(default)]
+  startup_delay_ms: Option<u64>,
+  /// Run inside this tmux session instead of a new terminal window, when tmux
+  /// is installed.
+  #[serde(default)]
+  tmux_session: Option<String>,
+  /// Extra environment variables for the agent.
+  #[serde(default)]
+  env: Option<HashMap<String, String>>,
+  /// Shell left running once the command finishes, and on Linux the one that
+  /// runs it; `$SHELL` (or `/bin/sh`) when not given. On Windows this picks
+  /// the shell that runs the command instead: `cmd` (the default),
+  /// `powershell` or `pwsh`.
+  #[serde(default)]
+  shell: Option<String>,
+  /// How long to wait for a launcher that should exit once the terminal is
+  /// open before giving up on it.
+  #[serde(default)]
+  timeout_ms: Option<u64>,
+  /// Whether to make sure the program exists before opening a terminal.
+  /// Defaults to on; turn it off for aliases and shell functions.
+  #[serde(default)]
+  check_command: Option<bool>,
+  /// Whether to copy the command's output to a log file in the app's data
+  /// directory.
+  #[serde(default)]
+  log_output: Option<bool>,
+  /// Whether to open a tab in an existing window instead of a new window,
+  /// where the terminal can. Falls back to a new window when none is open.
+  #[serde(default)]
+  reuse_window: Option<bool>,
+  /// Terminal profile to open the agent with (gnome-terminal, konsole, tilix,
+  /// iTerm, Terminal.app and Windows Terminal); ignored elsewhere.
+  #[serde(default)]
+  profile: Option<String>,
+  /// Refuse to launch when this many agents are already running. Overrides
+  /// the config's `max_concurrent_agents`.
+  #[serde(default)]
+  max_concurrent_agents: Option<usize>,
+  /// Run the command on this host over SSH, from a local terminal.
+  #[serde(default)]
+  ssh_host: Option<String>,
+  /// User to log in to `ssh_host` as; ssh's own default when unset.
+  #[serde(default)]
+  ssh_user: Option<String>,
+  /// Directory on `ssh_host` to run the command in.
+  #[serde(default)]
+  remote_working_directory: Option<String>,
+  /// Run the command without a terminal and stream its output as
+  /// `agent-output` events instead. Only for agents that don't need a tty.
+  #[serde(default)]
+  headless: Option<bool>,
+  /// Run the command in a pseudo-terminal owned by the app, for showing in
+  /// its own terminal panel, rather than in a terminal emulator.
+  #[serde(default)]
+  pty: Option<bool>,
+  /// How many more times to try a terminal that started but failed to open,
+  /// before moving on to the next one.
+  #[serde(default)]
+  max_retries: Option<u32>,
+  /// Wait before the first retry, doubled for each one after it.
+  #[serde(default)]
+  retry_delay_ms: Option<u64>,
+  /// Flags for the terminal emulator itself, for options there is no named
+  /// parameter for. See `linux_terminal_args` and
+  /// `WindowsTerminal::launch_args` for where each terminal gets them; the
+  /// AppleScript terminals, tmux and headless launches ignore them.
+  #[serde(default)]
+  extra_terminal_args: Option<Vec<String>>,
+  /// Size of the terminal in character cells. Either can be left out to use
+  /// the terminal's usual 24 rows or 80 columns.
+  #[serde(default)]
+  rows: Option<u16>,
+  #[serde(default)]
+  cols: Option<u16>,
+  /// Where to put the window's top left corner on screen.
+  #[serde(default)]
+  position: Option<WindowPosition>,
+  /// Turns line breaks in the command and its arguments into spaces instead
+  /// of refusing the launch.
+  #[serde(default)]
+  escape_newlines: Option<bool>,
+  /// Whether the window stays open, at a shell, once the agent exits; on by
+  /// default. Turning it off is the same as an `on_complete` of `close`.
+  #[serde(default)]
+  keep_open: Option<bool>,
+  /// What the window does once the agent exits; overrides `keep_open`.
+  #[serde(default)]
+  on_complete: Option<OnComplete>,
+  /// Refuses to launch the same command in the same working directory as an
+  /// agent launched, and still running, less than this long ago, which is
+  /// almost always a double-click. `0` allows it. This applies to the entries
+  /// of one `launch_agents` call as well.
+  #[serde(default)]
+  dedupe_window_ms: Option<u64>,
+  /// Longest command line, in bytes, that may be launched; longer ones are
+  /// refused with `CommandTooLong` before anything is started.
+  #[serde(default)]
+  max_command_length: Option<usize>,
+  /// Environment to activate before the command runs.
+  #[serde(default)]
+  activate: Option<ActivationSpec>,
+  /// On Windows, opens the classic console by starting its shell in a new
+  /// console directly instead of through `start`, so the agent's PID is the
+  /// shell's own and its exit code is reported. `start`'s options in
+  /// `extra_terminal_args` don't apply then, and `wt` isn't affected.
+  #[serde(default)]
+  direct_console: Option<bool>,
+  /// Group the agent is a member of; `launch_agent_group` sets it for each
+  /// agent it launches.
+  #[serde(default)]
+  group: Option<String>,
+  /// The log file chosen for this launch when `log_output` is on.
+  #[serde(skip)]
+  log_path: Option<String>,
+}
+
+impl AgentSpec {
+  /// The command as a single line for terminals that only take a string,
+  /// writing each word with `quote` when it was given as an argument vector.
+  fn command_line(&self, quote: fn(&str) -> String) -> String {
+    match &self.args {
+      Some(args) => {
+        let words: Vec<String> = std::iter::once(&self.command).chain(args).map(|arg| quote(arg)).collect();
+        words.join(" ")
+      }
+      None => self.command.clone(),
+    }
+  }
+
+  fn startup_delay_ms(&self) -> u64 {
+    self.startup_delay_ms.unwrap_or(DEFAULT_STARTUP_DELAY_MS)
+  }
+
+  fn shell(&self) -> String {
+    self
+      .shell
+      .clone()
+      .or_else(|| std::env::var("SHELL").ok().filter(|shell| !shell.is_empty()))
+      .unwrap_or_else(|| "/bin/sh".to_string())
+  }
+
+  fn env(&self) -> HashMap<String, String> {
+    self.env.clone().unwrap_or_default()
+  }
+
+  fn timeout_ms(&self) -> u64 {
+    self.timeout_ms.unwrap_or(DEFAULT_LAUNCH_TIMEOUT_MS)
+  }
+
+  fn max_retries(&self) -> u32 {
+    self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+  }
+
+  fn retry_delay_ms(&self) -> u64 {
+    self.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS)
+  }
+
+  fn reuse_window(&self) -> bool {
+    self.reuse_window.unwrap_or(false)
+  }
+
+  fn on_complete(&self) -> OnComplete {
+    match (&self.on_complete, self.keep_open) {
+      (Some(on_complete), _) => on_complete.clone(),
+      (None, Some(false)) => OnComplete::Close,
+      (None, _) => OnComplete::Interactive(None),
+    }
+  }
+
+  fn dedupe_window_ms(&self) -> u64 {
+    self.dedupe_window_ms.unwrap_or(DEFAULT_DEDUPE_WINDOW_MS)
+  }
+
+  fn max_command_length(&self) -> usize {
+    self.max_command_length.unwrap_or(DEFAULT_MAX_COMMAND_LENGTH)
+  }
+
+  fn extra_terminal_args(&self) -> &[String] {
+    self.extra_terminal_args.as_deref().unwrap_or_default()
+  }
+
+  fn terminal_preference(&self) -> &[String] {
+    self.terminal_preference.as_deref().unwrap_or_default()
+  }
+
+  /// Where and how big to open the window, if the caller said.
+  fn geometry(&self) -> Option<WindowGeometry> {
+    let size = (self.rows.is_some() || self.cols.is_some()).then(|| {
+      (self.cols.unwrap_or(pty::DEFAULT_COLS), self.rows.unwrap_or(pty::DEFAULT_ROWS))
+    });
+    (size.is_some() || self.position.is_some())
+      .then_some(WindowGeometry { size, position: self.position })
+  }
+}
+
+/// Picks the log file for a spec, already filled in from the config, whose
+/// output is to be logged.
+fn prepare_spec(app: &tauri::AppHandle, mut spec: AgentSpec) -> Result<AgentSpec, AgentError> {
+  if spec.log_output.unwrap_or(false) {
+    let data_dir = app
+      .path_resolver()
+      .app_data_dir()
+      .ok_or_else(|| AgentError::LogFailed("the app data directory is unknown".to_string()))?;
+    spec.log_path = Some(new_log_path(&data_dir.join("logs"))?);
+  }
+  Ok(spec)
+}
+
+/// Launches `spec` with the user's configured defaults, records it in the
+/// registry and starts watching for its exit. Every attempt is appended to
+/// the audit log.
+fn launch_agent(
+  app: &tauri::AppHandle,
+  registry: &AgentRegistry,
+  config: &TerminalConfig,
+  spec: &AgentSpec,
+) -> Result<AgentInfo, AgentError> {
+  launch_configured(app, registry, config, config.apply(spec))
+}
+
+/// `launch_agent` for a spec that `config` has already been applied to.
+fn launch_configured(
+  app: &tauri::AppHandle,
+  registry: &AgentRegistry,
+  config: &TerminalConfig,
+  spec: AgentSpec,
+) -> Result<AgentInfo, AgentError> {
+  let spec = prepare_spec(app, spec)?;
+  info!(
+    "Launching {:?} in {} (terminal: {})",
+    spec.command_line(shell_word),
+    spec.working_directory.as_deref().unwrap_or("the default directory"),
+    spec.terminal.as_deref().unwrap_or("default"),
+  );
+  let result = start_agent(app, registry, &spec);
+  match &result {
+    Ok(info) => info!("Started {} in {} as pid {}", info.id, info.terminal, info.pid),
+    Err(e) => warn!("Could not launch {:?}: {}", spec.command_line(shell_word), e),
+  }
+  let audit_log = config
+    .audit_log
+    .as_ref()
+    .map(std::path::PathBuf::from)
+    .or_else(|| app.path_resolver().app_data_dir().map(|dir| dir.join("audit.log")));
+  if let Some(path) = audit_log {
+    audit::record(&path, &spec, &result);
+  }
+  result
+}
+
+/// Launches a spec that `prepare_spec` has filled in.
+fn start_agent(
+  app: &tauri::AppHandle,
+  registry: &AgentRegistry,
+  spec: &AgentSpec,
+) -> Result<AgentInfo, AgentError> {
+  let slot = registry.reserve(spec)?;
+  let launching = AgentLaunching {
+    id: slot.id().to_string(),
+    command: spec.command_line(shell_word),
+    title: spec.title.clone(),
+  };
+  let _ = app.emit_all("agent-launching", launching);
+  if spec.pty.unwrap_or(false) {
+    return launch_pty_agent(app, slot, spec);
+  }
+  let mut launched = launch_terminal(spec)?;
+  let info = slot.register(spec, &launched);
+  let _ = app.emit_all("agent-launched", &info);
+  let readers = match launched.child.as_mut() {
+    Some(child) => stream_output(app, &info.id, child),
+    None => Vec::new(),
+  };
+
+  let app = app.clone();
+  let id = info.id.clone();
+  watch_agent(launched, move |exit_code| {
+    // Let the last of the output arrive before saying the agent is done
+    for reader in readers {
+      let _ = reader.join();
+    }
+    report_exit(&app, id, exit_code);
+  });
+
+  Ok(info)
+}
+
+/// Records that agent `id` exited and sends `agent-exited` for it.
+fn report_exit(app: &tauri::AppHandle, id: String, exit_code: Option<i32>) {
+  info!("{} exited with code {:?}", id, exit_code);
+  app.state::<AgentRegistry>().record_exit(&id, exit_code);
+  let _ = app.emit_all("agent-exited", AgentExited { id, exit_code });
+}
+
+/// Launches `spec` in a pseudo-terminal owned by the app, streaming what it
+/// writes as `agent-output` events; `send_agent_input` and `resize_agent`
+/// drive it from there.
+fn launch_pty_agent(
+  app: &tauri::AppHandle,
+  slot: registry::LaunchSlot<'_>,
+  spec: &AgentSpec,
+) -> Result<AgentInfo, AgentError> {
+  // The command line is the headless one; the reader thread writes the log
+  // so the agent's output stays a terminal
+  let (planned, _) = plan_launch(spec)?;
+  let command = Headless.build_command(&AgentSpec { log_path: None, ..planned });
+  let log = match &spec.log_path {
+    Some(path) => Some(
+      std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AgentError::LogFailed(format!("{}: {}", path, e)))?,
+    ),
+    None => None,
+  };
+  let pty::SpawnedPty { pid, mut child, reader, session } =
+    pty::spawn(
+      &command,
+      spec.rows.unwrap_or(pty::DEFAULT_ROWS),
+      spec.cols.unwrap_or(pty::DEFAULT_COLS),
+    )?;
+
+  let launched = LaunchedTerminal { pid, terminal: "pty".to_string(), tty: None, child: None };
+  let info = slot.register(spec, &launched);
+  let _ = app.emit_all("agent-launched", &info);
+  app.state::<pty::PtySessions>().insert(&info.id, session);
+
+  let output_app = app.clone();
+  let id = info.id.clone();
+  let forwarder = thread::spawn(move || {
+    pty::forward_output(reader, log, |data| {
+      let _ = output_app.emit_all("agent-output", AgentOutput { id: id.clone(), stream: "pty", data });
+    });
+  });
+
+  let app = app.clone();
+  let id = info.id.clone();
+  thread::spawn(move || {
+    let exit_code = child.wait().ok().map(|status| status.exit_code() as i32);
+    // Windows only ends the output once the app's end of the terminal closes
+    app.state::<pty::PtySessions>().remove(&id);
+    let _ = forwarder.join();
+    report_exit(&app, id, exit_code);
+  });
+
+  Ok(info)
+}
+
+/// Types `data` into the terminal of an agent launched with `pty`.
+#[tauri::command]
+fn send_agent_input(
+  sessions: tauri::State<'_, pty::PtySessions>,
+  id: String,
+  data: String,
+) -> Result<(), AgentError> {
+  sessions.write(&id, &data)
+}
+
+/// Resizes the terminal of an agent launched with `pty`, to follow the panel
+/// showing it.
+#[tauri::command]
+fn resize_agent(
+  sessions: tauri::State<'_, pty::PtySessions>,
+  id: String,
+  rows: u16,
+  cols: u16,
+) -> Result<(), AgentError> {
+  sessions.resize(&id, rows, cols)
+}
+
+/// Launches `spec`, with the options it leaves unset taken from the config.
+///
+/// The original `{ command }` payload, without a `spec`, still launches that
+/// command with the config's defaults. Every other option has to be passed
+/// inside `spec`.
+// Runs on the async runtime rather than the main thread so a slow launch
+// (e.g. macOS prompting for automation permission) doesn't freeze the window.
+#[tauri::command(async)]
+fn launch_agent_terminal(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  spec: Option<AgentSpec>,
+  command: Option<String>,
+) -> Result<AgentInfo, AgentError> {
+  let spec = spec.unwrap_or_else(|| AgentSpec { command: command.unwrap_or_default(), ..AgentSpec::default() });
+  launch_agent(&app, &registry, &config, &spec)
+}
+
+/// Like `launch_agent_terminal`, but takes the arguments for `spec.command`
+/// separately so they never need shell quoting.
+///
+/// Where the terminal can pass arguments through (most Linux emulators) they
+/// reach the program untouched; otherwise they are quoted into one command
+/// line for the shell the terminal runs.
+#[tauri::command(async)]
+fn launch_agent_terminal_args(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  spec: AgentSpec,
+  args: Vec<String>,
+) -> Result<AgentInfo, AgentError> {
+  launch_agent(&app, &registry, &config, &AgentSpec { args: Some(args), ..spec })
+}
+
+/// Launches the script at `script_path`, relative to `spec`'s
+/// `working_directory` when given, instead of a command line, so nothing
+/// about it needs quoting and its length doesn't matter. The script replaces
+/// `spec`'s `command` and `args`, so `command` can be left empty.
+///
+/// On macOS and Linux the script needs execute permission and is run as a
+/// program, so its `#!` line picks the interpreter. On Windows `.cmd`, `.bat`
+/// and `.exe` files run in the console's shell and `.ps1` files in
+/// PowerShell. The window stays open afterwards as `keep_open` and
+/// `on_complete` say, for reading what it printed.
+#[tauri::command(async)]
+fn launch_agent_script(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  spec: AgentSpec,
+  script_path: String,
+) -> Result<AgentInfo, AgentError> {
+  // The config's shell decides how a PowerShell script is run
+  let spec = script_spec(&config.apply(&spec), &script_path)?;
+  launch_configured(&app, &registry, &config, spec)
+}
+
+/// Returns `spec` running the script at `path` as its program, failing with
+/// `ScriptNotFound` or `ScriptNotExecutable` if it can't be run. A path with
+/// a line break in it is refused, since escaping it would name another file.
+fn script_spec(spec: &AgentSpec, path: &str) -> Result<AgentSpec, AgentError> {
+  if path.contains(['\n', '\r']) {
+    return Err(AgentError::MultilineOption("script_path".to_string()));
+  }
+  let missing = || AgentError::ScriptNotFound(path.to_string());
+  let file = match spec.working_directory.as_deref() {
+    // Joining an absolute path replaces the directory
+    Some(dir) => Path::new(&normalize_working_dir(dir)?).join(path),
+    None => std::path::PathBuf::from(path),
+  };
+  let file = file.canonicalize().map_err(|_| missing())?;
+  if !file.is_file() {
+    return Err(missing());
+  }
+  let script = strip_verbatim_prefix(&file.to_string_lossy());
+
+  #[cfg(unix)]
+  let shell = {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = file.metadata().map_err(|_| missing())?.permissions().mode();
+    if mode & 0o111 == 0 {
+      return Err(AgentError::ScriptNotExecutable(script));
+    }
+    spec.shell.clone()
+  };
+  #[cfg(windows)]
+  let shell = match file.extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase()) {
+    Some(extension) if ["cmd", "bat", "exe"].contains(&extension.as_str()) => spec.shell.clone(),
+    Some(extension) if extension == "ps1" => match WindowsShell::for_spec(spec) {
+      WindowsShell::Cmd => Some(WindowsShell::PowerShell.program().to_string()),
+      shell => Some(shell.program().to_string()),
+    },
+    _ => return Err(AgentError::ScriptNotExecutable(script)),
+  };
+  // An empty argument vector still gets the path quoted for whichever shell
+  // or terminal runs it
+  Ok(AgentSpec { command: script, args: Some(Vec::new()), shell, ..spec.clone() })
+}
+
+/// Launches several agents at once, one thread per agent.
+///
+/// Results are returned in the same order as `commands`; a failure only
+/// affects its own entry.
+#[tauri::command(async)]
+fn launch_agents(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  commands: Vec<AgentSpec>,
+) -> Vec<Result<AgentInfo, AgentError>> {
+  launch_all(&app, registry.inner(), config.inner(), &commands)
+}
+
+/// Launches `commands` as one group, `group_id`, which `kill_group` can then
+/// stop together and `list_groups` reports on. Launching into a group that
+/// already has members adds to it. Each agent's result is returned as for
+/// `launch_agents`.
+#[tauri::command(async)]
+fn launch_agent_group(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  group_id: String,
+  commands: Vec<AgentSpec>,
+) -> Result<Vec<Result<AgentInfo, AgentError>>, AgentError> {
+  if group_id.trim().is_empty() || group_id.trim() != group_id {
+    return Err(AgentError::InvalidGroupId(group_id));
+  }
+  let commands: Vec<AgentSpec> =
+    commands.into_iter().map(|spec| AgentSpec { group: Some(group_id.clone()), ..spec }).collect();
+  Ok(launch_all(&app, registry.inner(), config.inner(), &commands))
+}
+
+/// Stops every member of group `group_id` that is still running. Members
+/// stay registered, so they can still be restarted. Every member is tried;
+/// the first failure is returned.
+#[tauri::command(async)]
+fn kill_group(registry: tauri::State<'_, AgentRegistry>, group_id: String) -> Result<(), AgentError> {
+  let members = registry.group_members(&group_id);
+  if members.is_empty() {
+    return Err(AgentError::GroupNotFound(group_id));
+  }
+  let mut result = Ok(());
+  for member in members.iter().filter(|member| member.is_alive()) {
+    info!("Stopping {} of group {}", member.info.id, group_id);
+    if let (Err(e), Ok(())) = (stop_agent(member), &result) {
+      result = Err(e);
+    }
+  }
+  result
+}
+
+/// Lists every group of agents with its members and whether each is still
+/// running.
+#[tauri::command]
+fn list_groups(registry: tauri::State<'_, AgentRegistry>) -> Vec<registry::AgentGroup> {
+  registry.groups()
+}
+
+/// Launches each of `commands` on a thread of its own, returning their
+/// results in the same order.
+fn launch_all(
+  app: &tauri::AppHandle,
+  registry: &AgentRegistry,
+  config: &TerminalConfig,
+  commands: &[AgentSpec],
+) -> Vec<Result<AgentInfo, AgentError>> {
+  thread::scope(|scope| {
+    let launches: Vec<_> = commands
+      .iter()
+      .map(|spec| scope.spawn(|| launch_agent(app, registry, config, spec)))
+      .collect();
+    launches
+      .into_iter()
+      .map(|launch| {
+        launch
+          .join()
+          .unwrap_or_else(|_| Err(AgentError::SpawnFailed("launch thread panicked".to_string())))
+      })
+      .collect()
+  })
+}
+
+/// Checks whether agent `id` is still running, for UIs that poll rather than
+/// listen for `agent-exited`. The process is probed on every call.
+#[tauri::command]
+fn agent_status(registry: tauri::State<'_, AgentRegistry>, id: String) -> AgentStatus {
+  registry.get(&id).map_or(AgentStatus::Unknown, |handle| handle.status())
+}
+
+/// Lists the agents launched this session whose processes are still running.
+#[tauri::command]
+fn list_active_agents(registry: tauri::State<'_, AgentRegistry>) -> Vec<AgentInfo> {
+  registry.list_active()
+}
+
+/// Trims `command`, rejecting it if nothing is left to run.
+fn validate_command(command: &str) -> Result<&str, AgentError> {
+  let command = command.trim();
+  if command.is_empty() {
+    Err(AgentError::EmptyCommand)
+  } else {
+    Ok(command)
+  }
+}
+
+/// Opens a terminal running `command` on the current platform.
+///
+/// The launchers from `plan_launch` are tried in order; how each terminal is
+/// started lives in its `TerminalLauncher` implementation.
+fn launch_terminal(spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+  let (spec, launchers) = plan_launch(spec)?;
+  #[cfg(any(target_os = "macos", target_os = "windows"))]
+  if spec.command_line(shell_word).len() > SCRIPT_FILE_THRESHOLD {
+    let (spec, script) = script_file_spec(&spec)?;
+    // The script removes itself once it starts, so only a launch that never
+    // got that far leaves it behind
+    return try_launchers(&spec, &launchers).inspect_err(|_| {
+      let _ = std::fs::remove_file(&script);
+    });
+  }
+  try_launchers(&spec, &launchers)
+}
+
+/// Tries each of `launchers` on the planned `spec` until one opens.
+fn try_launchers(
+  spec: &AgentSpec,
+  launchers: &[Box<dyn TerminalLauncher>],
+) -> Result<LaunchedTerminal, AgentError> {
+  let names: Vec<&str> = launchers.iter().map(|launcher| launcher.name()).collect();
+  debug!("Terminals to try: {}", names.join(", "));
+  if let [launcher] = launchers {
+    return launch_with_retries(launcher.as_ref(), spec);
+  }
+
+  // Spawning doesn't wait for the emulator, so one that hangs can't stall the
+  // loop and the launch timeout doesn't apply here. Only installed emulators
+  // are tried, so each failure is worth reporting.
+  let mut failures = Vec::new();
+  let mut denied = Vec::new();
+  for launcher in launchers {
+    match launch_with_retries(launcher.as_ref(), spec) {
+      Ok(launched) => return Ok(launched),
+      Err(e) => {
+        debug!("{} failed: {}", launcher.name(), e);
+        if let AgentError::PermissionDenied(terminal) = &e {
+          denied.push(terminal.clone());
+        }
+        failures.push((launcher.name().to_string(), failure_reason(e)));
+      }
+    }
+  }
+  // Then granting the permission is the one thing that would get a terminal open
+  if !denied.is_empty() && denied.len() == failures.len() {
+    return Err(AgentError::PermissionDenied(denied.remove(0)));
+  }
+  Err(AgentError::AllTerminalsFailed(failures))
+}
+
+/// Shells a command too long for its launcher can be run from a script in.
+#[cfg(any(target_os = "macos", target_os = "windows", test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScriptShell {
+  /// `sh` and its kin, on macOS and under WSL.
+  Posix,
+  #[cfg(any(target_os = "windows", test))]
+  Cmd,
+  /// Windows PowerShell or PowerShell 7+.
+  #[cfg(any(target_os = "windows", test))]
+  PowerShell,
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", test))]
+impl ScriptShell {
+  #[cfg(any(target_os = "macos", target_os = "windows"))]
+  fn extension(self) -> &'static str {
+    match self {
+      ScriptShell::Posix => "sh",
+      #[cfg(any(target_os = "windows", test))]
+      ScriptShell::Cmd => "cmd",
+      #[cfg(any(target_os = "windows", test))]
+      ScriptShell::PowerShell => "ps1",
+    }
+  }
+
+  /// Returns what a script at `path` that runs `line` holds, and the command
+  /// that runs it in place of `line`. The script deletes itself as it runs.
+  ///
+  /// The POSIX script is sourced, so aliases and functions from the shell the
+  /// terminal opened still apply. PowerShell reads the script in rather than
+  /// running the file, which the execution policy could forbid.
+  fn script(self, line: &str, path: &str) -> (String, String) {
+    match self {
+      ScriptShell::Posix => {
+        let quoted = shell_quote(path);
+        (format!("rm -f {}\n{}\n", quoted, line), format!(". {}", quoted))
+      }
+      #[cfg(any(target_os = "windows", test))]
+      ScriptShell::Cmd => {
+        // `%` expands in batch files where it wouldn't on the command line.
+        // `(goto)` ends the script before `del` removes it from under cmd
+        let contents = format!("@{}\r\n@(goto) 2>nul & del \"%~f0\"\r\n", line.replace('%', "%%"));
+        (contents, format!("call {}", cmd_word(path)))
+      }
+      #[cfg(any(target_os = "windows", test))]
+      ScriptShell::PowerShell => {
+        let quoted = ps_quote(path);
+        let command = format!(
+          "$agentScript = Get-Content -Raw -LiteralPath {0}; Remove-Item -LiteralPath {0}; \
+           . ([scriptblock]::Create($agentScript))",
+          quoted
+        );
+        (line.to_string(), command)
+      }
+    }
+  }
+}
+
+/// Writes the command of the planned `spec` to a fresh script file and
+/// returns `spec` running that script instead, with the script's path.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn script_file_spec(spec: &AgentSpec) -> Result<(AgentSpec, std::path::PathBuf), AgentError> {
+  static NEXT_SCRIPT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+  #[cfg(target_os = "macos")]
+  let (shell, line) = (ScriptShell::Posix, spec.command_line(shell_word));
+  #[cfg(target_os = "windows")]
+  let (shell, line) = match WindowsShell::for_spec(spec) {
+    _ if spec.wsl.unwrap_or(false) => (ScriptShell::Posix, spec.command_line(shell_word)),
+    WindowsShell::Cmd => (ScriptShell::Cmd, spec.command_line(cmd_word)),
+    WindowsShell::PowerShell | WindowsShell::Pwsh => match spec.args {
+      Some(_) => (ScriptShell::PowerShell, format!("& {}", spec.command_line(ps_word))),
+      None => (ScriptShell::PowerShell, spec.command.clone()),
+    },
+  };
+
+  let seq = NEXT_SCRIPT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  let path = std::env::temp_dir().join(format!(
+    "agentic-sync-{}-{}.{}",
+    std::process::id(),
+    seq,
+    shell.extension()
+  ));
+  let path_str = path.to_string_lossy();
+  #[cfg(target_os = "windows")]
+  let path_str = match shell {
+    ScriptShell::Posix => windows_path_to_wsl(&path_str).into(),
+    _ => path_str,
+  };
+  let (contents, command) = shell.script(&line, &path_str);
+  std::fs::write(&path, contents).map_err(|e| AgentError::SpawnFailed(format!("{}: {}", path.display(), e)))?;
+  debug!("Running the {}-byte command from {}", line.len(), path.display());
+  Ok((AgentSpec { command, args: None, ..spec.clone() }, path))
+}
+
+/// Launches `launcher`, trying again with a growing delay while it starts but
+/// fails to open (no display yet, the session still starting up). A terminal
+/// that can't be spawned at all isn't installed, so isn't retried.
+///
+/// Nor is one whose failure could have been the agent's own, see
+/// `retry_is_safe`, since running it again would repeat what it did.
+fn launch_with_retries(
+  launcher: &dyn TerminalLauncher,
+  spec: &AgentSpec,
+) -> Result<LaunchedTerminal, AgentError> {
+  // Everything the terminal is given, environment values included, so only
+  // logged when asked for
+  trace!("Running {}", describe_command(&launcher.build_command(spec)));
+  let mut delay = spec.retry_delay_ms();
+  for _ in 0..spec.max_retries() {
+    let started = std::time::Instant::now();
+    match launcher.launch(spec) {
+      Err(AgentError::LaunchFailed(reason)) if retry_is_safe(spec, started.elapsed()) => {
+        info!("{} failed to open ({}), retrying in {} ms", launcher.name(), reason, delay);
+        thread::sleep(std::time::Duration::from_millis(delay));
+        delay = delay.saturating_mul(2);
+      }
+      result => return result,
+    }
+  }
+  launcher.launch(spec)
+}
+
+/// Whether a terminal that failed to open `elapsed` after it was launched
+/// can't have run the agent yet. Terminals such as kitty exit with the
+/// agent's status, so a quick failure is only the terminal's own if it came
+/// before the startup delay was up, or a shell stays open after the agent
+/// and would have kept the terminal from exiting.
+fn retry_is_safe(spec: &AgentSpec, elapsed: std::time::Duration) -> bool {
+  elapsed < std::time::Duration::from_millis(spec.startup_delay_ms())
+    || matches!(spec.on_complete(), OnComplete::Interactive(_))
+}
+
+/// What went wrong with one terminal, without the error's own preamble.
+fn failure_reason(error: AgentError) -> String {
+  match error {
+    AgentError::SpawnFailed(reason) | AgentError::LaunchFailed(reason) => reason,
+    error => error.to_string(),
+  }
+}
+
+/// Turns `dir` into the absolute path every terminal and shell agrees on:
+/// a leading `~` is the home directory, a relative path is taken from the
+/// app's current directory, and symlinks are resolved. Fails with
+/// `InvalidWorkingDir` unless that is an existing directory.
+fn normalize_working_dir(dir: &str) -> Result<String, AgentError> {
+  let invalid = || AgentError::InvalidWorkingDir(dir.to_string());
+  let home_relative = dir
+    .strip_prefix('~')
+    .filter(|rest| rest.is_empty() || rest.starts_with('/') || (cfg!(windows) && rest.starts_with('\\')));
+  let path = match home_relative {
+    Some(rest) => {
+      let home = tauri::api::path::home_dir().ok_or_else(invalid)?;
+      home.join(rest.trim_start_matches(['/', '\\']))
+    }
+    None => std::path::PathBuf::from(dir),
+  };
+  // canonicalize makes relative paths absolute against the current directory
+  let resolved = path.canonicalize().map_err(|_| invalid())?;
+  if !resolved.is_dir() {
+    return Err(invalid());
+  }
+  Ok(strip_verbatim_prefix(&resolved.to_string_lossy()))
+}
+
+/// Undoes the `\\?\` form Windows gives canonical paths, which cmd can't
+/// `cd` into and most programs don't expect. Other paths are returned as-is.
+fn strip_verbatim_prefix(path: &str) -> String {
+  match path.strip_prefix(r"\\?\") {
+    Some(rest) => match rest.strip_prefix(r"UNC\") {
+      Some(share) => format!(r"\\{}", share),
+      None => rest.to_string(),
+    },
+    None => path.to_string(),
+  }
+}
+
+/// Validates `spec` and picks the launchers that could open it, best first.
+/// Only Linux has more than one to fall back on.
+///
+/// The returned spec has its command trimmed, as `build_command` expects.
+fn plan_launch(spec: &AgentSpec) -> Result<(AgentSpec, Vec<Box<dyn TerminalLauncher>>), AgentError> {
+  let command = validate_command(&spec.command)?.to_string();
+  let spec = guard_newlines(AgentSpec { command, ..spec.clone() })?;
+  let (length, limit) = (spec.command_line(shell_word).len(), spec.max_command_length());
+  if length > limit {
+    return Err(AgentError::CommandTooLong { length, limit });
+  }
+  validate_env(&spec.env())?;
+  validate_extra_terminal_args(&spec)?;
+  let wsl = spec.wsl.unwrap_or(false);
+  let spec = match spec.working_directory.as_deref() {
+    // Linux-style paths under WSL live inside the distro and can't be checked here
+    Some(dir) if !(wsl && dir.starts_with('/')) => {
+      AgentSpec { working_directory: Some(normalize_working_dir(dir)?), ..spec }
+    }
+    _ => spec,
+  };
+  // A venv is looked for under the working directory, so that has to be resolved first
+  let spec = match &spec.activate {
+    Some(activation) => activate_spec(&spec, activation)?,
+    None => spec,
+  };
+  // From here on an SSH launch is just a local terminal running ssh
+  let spec = match spec.ssh_host.as_deref() {
+    Some(host) => ssh_spec(&spec, host)?,
+    None => spec,
+  };
+
+  #[cfg(not(target_os = "windows"))]
+  if wsl || spec.distro.is_some() {
+    return Err(AgentError::WslUnavailable("WSL launching is only available on Windows".to_string()));
+  }
+
+  // Programs inside a WSL distro can't be looked up from the Windows side
+  if spec.check_command.unwrap_or(true) && !wsl {
+    if let Some(program) = command_program(&spec) {
+      if !program_exists(&program, spec.working_directory.as_deref(), spec.timeout_ms()) {
+        return Err(AgentError::CommandNotFound(program));
+      }
+    }
+  }
+
+  if spec.headless.unwrap_or(false) || spec.pty.unwrap_or(false) {
+    return Ok((spec, vec![Box::new(Headless)]));
+  }
+
+  // Without tmux installed, fall through to opening a normal terminal window
+  if let Some(session) = spec.tmux_session.as_deref() {
+    if find_on_path("tmux").is_some() {
+      let tmux = Tmux::for_session(session, spec.timeout_ms())?;
+      return Ok((spec, vec![Box::new(tmux)]));
+    }
+  }
+
+  let launchers = select_terminals(&spec)?.into_iter().map(|(launcher, _)| launcher).collect();
+  Ok((spec, launchers))
+}
+
+/// Where a terminal that could be launched came from, as reported by
+/// `resolve_terminal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+// The desktop's own choices are only looked up on Linux
+#[allow(dead_code)]
+enum TerminalSource {
+  /// The launch's `terminal`, or the one set in the config.
+  Requested,
+  /// The launch's `terminal_preference`, or the config's.
+  Preference,
+  /// `$TERMINAL`, or `AGENTIC_SYNC_MACOS_TERMINAL` on macOS.
+  Env,
+  /// `xdg-terminal-exec`.
+  Xdg,
+  /// GNOME's or Debian's configured default terminal.
+  SystemDefault,
+  /// The app's own list for the platform.
+  Builtin,
+}
+
+/// A terminal that could be launched, and where it came from.
+type FoundTerminal = (Box<dyn TerminalLauncher>, TerminalSource);
+
+/// Picks the terminals to try for `spec` on this platform, in order: its
+/// `terminal`, then its `terminal_preference`, then the platform's own
+/// choices, keeping only those that are installed. Each comes with where it
+/// was found.
+fn select_terminals(spec: &AgentSpec) -> Result<Vec<FoundTerminal>, AgentError> {
+  #[cfg(not(target_os = "linux"))]
+  let terminal = spec.terminal.as_deref();
+  #[cfg(target_os = "windows")]
+  let wsl = spec.wsl.unwrap_or(false);
+
+  #[cfg(target_os = "macos")]
+  {
+    let default_terminal = std::env::var(MacTerminal::DEFAULT_ENV).ok();
+    let mut found: Vec<(Option<Box<dyn TerminalLauncher>>, TerminalSource)> = Vec::new();
+    if let Some(name) = terminal {
+      found.push((mac_launcher(name)?, TerminalSource::Requested));
+    }
+    let preferred = spec.terminal_preference().iter().map(|name| mac_launcher(name).ok().flatten());
+    found.extend(preferred.map(|launcher| (launcher, TerminalSource::Preference)));
+    if let (None, Some(name)) = (terminal, default_terminal.as_deref()) {
+      found.push((mac_launcher(name)?, TerminalSource::Env));
+    }
+    // Fall back to Terminal.app, which is always present, if none of those are installed
+    found.push((Some(Box::new(MacTerminal::Terminal)), TerminalSource::Builtin));
+    let mut launchers: Vec<FoundTerminal> = Vec::new();
+    for (launcher, source) in found {
+      let Some(launcher) = launcher else {
+        continue;
+      };
+      if !launchers.iter().any(|(tried, _)| tried.name() == launcher.name()) {
+        launchers.push((launcher, source));
+      }
+    }
+    Ok(launchers)
+  }
+
+  #[cfg(target_os = "windows")]
+  {
+    // Prefer Windows Terminal when it's installed unless the caller forces one
+    let win_terminals = match terminal {
+      Some(name) => {
+        let choice = WindowsTerminal::from_name(name)
+          .ok_or_else(|| AgentError::UnsupportedTerminal(name.to_string()))?;
+        if !choice.is_installed() {
+          return Err(AgentError::TerminalNotInstalled("Windows Terminal (wt.exe)".to_string()));
+        }
+        vec![(choice, TerminalSource::Requested)]
+      }
+      None => {
+        let preferred = spec.terminal_preference().iter().filter_map(|name| WindowsTerminal::from_name(name));
+        let builtin = [WindowsTerminal::WindowsTerminal, WindowsTerminal::Cmd];
+        let choices = preferred
+          .map(|choice| (choice, TerminalSource::Preference))
+          .chain(builtin.map(|choice| (choice, TerminalSource::Builtin)));
+        let mut terminals: Vec<(WindowsTerminal, TerminalSource)> = Vec::new();
+        for (choice, source) in choices {
+          if choice.is_installed() && !terminals.iter().any(|(added, _)| *added == choice) {
+            terminals.push((choice, source));
+          }
+        }
+        terminals
+      }
+    };
+
+    if wsl && find_on_path("wsl.exe").is_none() {
+      return Err(AgentError::WslUnavailable("wsl.exe was not found on PATH".to_string()));
+    }
+    // WSL always runs bash inside the distro, so the shell only matters otherwise
+    if let (Some(name), false) = (spec.shell.as_deref(), wsl) {
+      let shell = WindowsShell::from_name(name).ok_or_else(|| AgentError::UnsupportedShell(name.to_string()))?;
+      if find_on_path(shell.program()).is_none() {
+        return Err(AgentError::TerminalNotInstalled(shell.program().to_string()));
+      }
+    }
+    let launchers = win_terminals
+      .into_iter()
+      .map(|(terminal, source)| (Box::new(terminal) as Box<dyn TerminalLauncher>, source))
+      .collect();
+    Ok(launchers)
+  }
+
+  #[cfg(target_os = "linux")]
+  {
+    linux_launchers(spec, &DesktopTerminals::detect(), LINUX_TERMINALS, &PackagedTerminals::detect())
+  }
+}
+
+/// Rewrites `spec` to run its command inside `activation`'s environment, by
+/// prefixing the command line with what activates it in the shell that will
+/// run it. Commands run over SSH or in WSL get the POSIX form, and a venv is
+/// only checked for when it is on this machine.
+fn activate_spec(spec: &AgentSpec, activation: &ActivationSpec) -> Result<AgentSpec, AgentError> {
+  let remote = spec.ssh_host.is_some() || spec.wsl.unwrap_or(false);
+  match activation {
+    ActivationSpec::Venv(name) | ActivationSpec::Conda(name) if name.trim().is_empty() => {
+      return Err(AgentError::InvalidEnvironment("no environment was named".to_string()));
+    }
+    ActivationSpec::Venv(path) if !remote => {
+      let dir = match &spec.working_directory {
+        Some(working_directory) => Path::new(working_directory).join(path),
+        None => Path::new(path).to_path_buf(),
+      };
+      if !dir.is_dir() {
+        return Err(AgentError::InvalidEnvironment(format!("virtual environment {} does not exist", path)));
+      }
+    }
+    _ => {}
+  }
+
+  #[cfg(target_os = "windows")]
+  if !remote {
+    return windows_activated_spec(spec, activation, WindowsShell::for_spec(spec));
+  }
+  Ok(posix_activated_spec(spec, activation))
+}
+
+/// `activate_spec` for a POSIX shell. The venv's script is read with `.`,
+/// which `/bin/sh` has where `source` may not be, and conda's shell hook is
+/// loaded first since a non-interactive shell won't have `conda activate`.
+fn posix_activated_spec(spec: &AgentSpec, activation: &ActivationSpec) -> AgentSpec {
+  let line = spec.command_line(shell_word);
+  let (command, args) = match activation {
+    ActivationSpec::Venv(path) => {
+      let script = format!("{}/bin/activate", path.trim_end_matches('/'));
+      (format!(". {} && {}", shell_quote(&script), line), None)
+    }
+    ActivationSpec::Conda(name) => {
+      let hook = "eval \"$(conda shell.posix hook)\"";
+      (format!("{} && conda activate {} && {}", hook, shell_quote(name), line), None)
+    }
+    // `nix develop -c` takes a program and its arguments rather than a line
+    ActivationSpec::Nix(flake) => {
+      let mut args: Vec<String> = std::iter::once("develop".to_string()).chain(flake.clone()).collect();
+      args.push("-c".to_string());
+      match &spec.args {
+        Some(argv) => args.extend(std::iter::once(spec.command.clone()).chain(argv.iter().cloned())),
+        None => args.extend(["sh".to_string(), "-c".to_string(), line]),
+      }
+      ("nix".to_string(), Some(args))
+    }
+  };
+  AgentSpec { command, args, activate: None, ..spec.clone() }
+}
+
+/// `activate_spec` for a Windows shell. conda is a batch file, so cmd has to
+/// `call` it to carry on afterwards, as with a venv's `activate.bat`.
+#[cfg(any(target_os = "windows", test))]
+fn windows_activated_spec(
+  spec: &AgentSpec,
+  activation: &ActivationSpec,
+  shell: WindowsShell,
+) -> Result<AgentSpec, AgentError> {
+  let scripts = |path: &str, script: &str| {
+    format!("{}\\Scripts\\{}", path.trim_end_matches(['\\', '/']), script)
+  };
+  let ps_line = || match spec.args {
+    Some(_) => format!("& {}", spec.command_line(ps_word)),
+    None => spec.command.clone(),
+  };
+  // Windows PowerShell has no `&&`
+  let command = match (activation, shell) {
+    (ActivationSpec::Nix(_), _) => {
+      return Err(AgentError::InvalidEnvironment("Nix is only available on Windows through WSL".to_string()));
+    }
+    (ActivationSpec::Venv(path), WindowsShell::Cmd) => {
+      format!("call {} && {}", cmd_word(&scripts(path, "activate.bat")), spec.command_line(cmd_word))
+    }
+    (ActivationSpec::Conda(name), WindowsShell::Cmd) => {
+      format!("call conda activate {} && {}", cmd_word(name), spec.command_line(cmd_word))
+    }
+    (ActivationSpec::Venv(path), _) => format!("& {}; {}", ps_quote(&scripts(path, "Activate.ps1")), ps_line()),
+    (ActivationSpec::Conda(name), _) => format!("conda activate {}; {}", ps_word(name), ps_line()),
+  };
+  Ok(AgentSpec { command, args: None, activate: None, ..spec.clone() })
+}
+
+/// Rewrites `spec` to run on `host`: the terminal runs
+/// `ssh -t -- [user@]host LINE`, where LINE changes to the remote working
+/// directory, exports the environment and runs the command.
+///
+/// LINE is a single argument to ssh, so only the remote shell interprets it.
+/// `-t` gives the remote command a tty, which interactive agents need, and
+/// `--` keeps a host starting with `-` from being read as an option.
+///
+/// The host and user must be non-empty and free of whitespace. The user also
+/// can't have an `@`, which would move where the host starts, or a leading
+/// `-`.
+fn ssh_spec(spec: &AgentSpec, host: &str) -> Result<AgentSpec, AgentError> {
+  let valid = |name: &str| !name.is_empty() && !name.contains(char::is_whitespace);
+  if !valid(host) {
+    return Err(AgentError::InvalidSshHost(host.to_string()));
+  }
+  let target = match spec.ssh_user.as_deref() {
+    Some(user) if !valid(user) || user.contains('@') || user.starts_with('-') => {
+      return Err(AgentError::InvalidSshUser(user.to_string()));
+    }
+    Some(user) => format!("{}@{}", user, host),
+    None => host.to_string(),
+  };
+
+  let mut line = format!("{}{}", env_export_prefix(&spec.env()), spec.command_line(shell_word));
+  if let Some(dir) = &spec.remote_working_directory {
+    line = format!("cd {} && {}", shell_quote(dir), line);
+  }
+  Ok(AgentSpec {
+    command: "ssh".to_string(),
+    args: Some(vec!["-t".to_string(), "--".to_string(), target, line]),
+    env: None,
+    ssh_host: None,
+    ..spec.clone()
+  })
+}
+
+/// Shows the command `launch_agent_terminal` would run for `spec`, without
+/// opening anything. Arguments are shell-quoted, preceded by the variables
+/// set for it and the directory it runs in.
+///
+/// On Linux this is the first installed emulator, which is the one a launch
+/// would try first.
+#[tauri::command(async)]
+fn preview_launch_command(
+  app: tauri::AppHandle,
+  config: tauri::State<'_, TerminalConfig>,
+  spec: AgentSpec,
+) -> Result<String, AgentError> {
+  let spec = prepare_spec(&app, config.apply(&spec))?;
+  let (spec, launchers) = plan_launch(&spec)?;
+  let launcher = launchers.first().ok_or_else(|| AgentError::NoTerminalFound(Vec::new()))?;
+  Ok(describe_command(&launcher.build_command(&spec)))
+}
+
+/// The terminal `resolve_terminal` found and how, serialized as
+/// `{ "terminal": "kitty", "source": "xdg" }`.
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+struct ResolvedTerminal {
+  terminal: String,
+  source: TerminalSource,
+}
+
+/// Names the terminal a launch would open right now, and where it came from,
+/// picked the same way `launch_agent_terminal` picks one: the terminal set in
+/// the config, then `preference` (or the config's list), then the platform's
+/// own choices. On Linux those are `$TERMINAL`, then `xdg-terminal-exec`,
+/// then the desktop's default terminal, then the built-in list. Only checks
+/// what is installed; nothing is opened.
+#[tauri::command(async)]
+fn resolve_terminal(
+  config: tauri::State<'_, TerminalConfig>,
+  preference: Option<Vec<String>>,
+) -> Result<ResolvedTerminal, AgentError> {
+  let spec = config.apply(&AgentSpec { terminal_preference: preference, ..AgentSpec::default() });
+  first_terminal(select_terminals(&spec)?)
+}
+
+/// The terminal that would be tried first.
+fn first_terminal(
+  launchers: Vec<FoundTerminal>,
+) -> Result<ResolvedTerminal, AgentError> {
+  let (launcher, source) = launchers.first().ok_or_else(|| AgentError::NoTerminalFound(Vec::new()))?;
+  Ok(ResolvedTerminal { terminal: launcher.name().to_string(), source: *source })
+}
+
+/// Renders `command` as a shell line: `cd DIR && NAME=value program args`.
+fn describe_command(command: &Command) -> String {
+  let mut env: Vec<String> = command
+    .get_envs()
+    .filter_map(|(name, value)| {
+      Some(format!("{}={}", name.to_string_lossy(), shell_word(&value?.to_string_lossy())))
+    })
+    .collect();
+  env.sort();
+
+  let argv = std::iter::once(command.get_program())
+    .chain(command.get_args())
+    .map(|arg| shell_word(&arg.to_string_lossy()));
+  let line = env.into_iter().chain(argv).collect::<Vec<_>>().join(" ");
+  match command.get_current_dir() {
+    Some(dir) => format!("cd {} && {}", shell_word(&dir.to_string_lossy()), line),
+    None => line,
+  }
+}
+
+/// Builds the tmux invocation that runs `command` in `session`.
+///
+/// A new detached session is created unless it already exists, in which case
+/// the command gets a new window there. tmux prints the PID of the pane's shell
+/// so the agent can be tracked like any other process. `follow_up` runs once
+/// the command is done, such as a shell to keep the pane open.
+fn tmux_args(
+  session: &str,
+  session_exists: bool,
+  command: &str,
+  follow_up: Option<&str>,
+  working_directory: Option<&str>,
+  title: Option<&str>,
+) -> Vec<String> {
+  let mut args: Vec<String> = if session_exists {
+    vec!["new-window".to_string(), "-t".to_string(), format!("{}:", session)]
+  } else {
+    vec!["new-session".to_string(), "-d".to_string(), "-s".to_string(), session.to_string()]
+  };
+  if let Some(title) = title {
+    args.extend(["-n".to_string(), title.to_string()]);
+  }
+  if let Some(dir) = working_directory {
+    args.extend(["-c".to_string(), dir.to_string()]);
+  }
+  args.extend(["-P".to_string(), "-F".to_string(), "#{pane_pid}".to_string()]);
+  args.push(match follow_up {
+    Some(follow_up) => format!("{}; {}", command, follow_up),
+    None => command.to_string(),
+  });
+  args
+}
+
+/// Returns the entries of `programs` that can be found on `PATH` or are
+/// installed in `packaged`, in order.
+#[cfg(any(target_os = "linux", test))]
+fn programs_on_path(programs: &[&str], packaged: &PackagedTerminals) -> Vec<String> {
+  programs
+    .iter()
+    .filter(|program| find_on_path(program).is_some() || packaged.launcher(program).is_some())
+    .map(|program| program.to_string())
+    .collect()
+}
+
+/// Lists the terminals `launch_agent_terminal` can use on this machine, in the
+/// order it prefers them. The names are valid values for its `terminal` argument.
+#[tauri::command]
+fn detect_available_terminals() -> Vec<String> {
+  #[cfg(target_os = "macos")]
+  let installed = [MacTerminal::Terminal, MacTerminal::ITerm]
+    .into_iter()
+    .filter(|terminal| terminal.is_installed())
+    .map(|terminal| terminal.name().to_string())
+    .chain(
+      MAC_CLI_TERMINALS
+        .iter()
+        .filter(|(program, binary)| find_mac_cli_terminal(program, binary, &mac_app_dirs()).is_some())
+        .map(|(program, _)| program.to_string()),
+    )
+    .collect();
+
+  #[cfg(target_os = "windows")]
+  let installed = [WindowsTerminal::WindowsTerminal, WindowsTerminal::Cmd]
+    .into_iter()
+    .filter(|terminal| terminal.is_installed())
+    .map(|terminal| terminal.name().to_string())
+    .collect();
+
+  #[cfg(target_os = "linux")]
+  let installed = programs_on_path(LINUX_TERMINALS, &PackagedTerminals::detect());
+
+  installed
+}
+
+/// Everything about this machine and the app's setup that bears on launching
+/// agents, for pasting into a bug report.
+#[derive(Debug, serde::Serialize)]
+struct DiagnosticsReport {
+  version: &'static str,
+  /// `linux`, `macos` or `windows`.
+  os: &'static str,
+  arch: &'static str,
+  /// What `detect_available_terminals` lists.
+  terminals: Vec<String>,
+  /// What `resolve_terminal` answers with no preference, or why it can't.
+  default_terminal: Option<ResolvedTerminal>,
+  default_terminal_error: Option<AgentError>,
+  /// The shell agents run in, or the Windows one, once the config is applied.
+  shell: String,
+  tmux_installed: bool,
+  /// The config file, if there is one; `config` is the defaults otherwise.
+  config_path: Option<String>,
+  config: TerminalConfig,
+  active_agents: usize,
+}
+
+/// Gathers the report `diagnostics` returns.
+fn diagnostics_report(config: &TerminalConfig, registry: &AgentRegistry) -> DiagnosticsReport {
+  let spec = config.apply(&AgentSpec::default());
+  let (default_terminal, default_terminal_error) = match select_terminals(&spec).and_then(first_terminal) {
+    Ok(resolved) => (Some(resolved), None),
+    Err(e) => (None, Some(e)),
+  };
+  #[cfg(target_os = "windows")]
+  let shell = WindowsShell::for_spec(&spec).program().to_string();
+  #[cfg(not(target_os = "windows"))]
+  let shell = spec.shell();
+  DiagnosticsReport {
+    version: env!("CARGO_PKG_VERSION"),
+    os: std::env::consts::OS,
+    arch: std::env::consts::ARCH,
+    terminals: detect_available_terminals(),
+    default_terminal,
+    default_terminal_error,
+    shell,
+    tmux_installed: find_on_path("tmux").is_some(),
+    config_path: config::config_path().filter(|path| path.is_file()).map(|path| path.display().to_string()),
+    config: config.clone(),
+    active_agents: registry.list_active().len(),
+  }
+}
+
+/// Reports the app's version, the platform, the terminals found and the one
+/// a launch would use, the config in effect and how many agents are running,
+/// so a bug report can include all of it in one go.
+#[tauri::command(async)]
+fn diagnostics(
+  config: tauri::State<'_, TerminalConfig>,
+  registry: tauri::State<'_, AgentRegistry>,
+) -> DiagnosticsReport {
+  diagnostics_report(&config, &registry)
+}
+
+/// Returns whether a process with `pid` is currently running.
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+  Command::new("kill")
+    .args(["-0", &pid.to_string()])
+    .output()
+    .is_ok_and(|output| output.status.success())
+}
+
+#[cfg(windows)]
+fn process_exists(pid: u32) -> bool {
+  hidden_console(&mut Command::new("tasklist"))
+    .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+    .output()
+    .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)))
+}
+
+/// When the process running as `pid` started, in milliseconds since the Unix
+/// epoch, or `None` if that can't be found out.
+#[cfg(unix)]
+fn process_started_at(pid: u32) -> Option<u64> {
+  let output = Command::new("ps").args(["-o", "etime=", "-p", &pid.to_string()]).output().ok()?;
+  let elapsed = parse_elapsed(String::from_utf8_lossy(&output.stdout).trim())?;
+  let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?;
+  (now.as_millis() as u64).checked_sub(elapsed * 1000)
+}
+
+#[cfg(windows)]
+fn process_started_at(pid: u32) -> Option<u64> {
+  let script = format!("([DateTimeOffset](Get-Process -Id {}).StartTime).ToUnixTimeMilliseconds()", pid);
+  let output = hidden_console(&mut Command::new("powershell"))
+    .args(["-NoProfile", "-Command", &script])
+    .output()
+    .ok()?;
+  String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Parses the `[[dd-]hh:]mm:ss` elapsed time `ps` prints into seconds.
+#[cfg(any(unix, test))]
+fn parse_elapsed(elapsed: &str) -> Option<u64> {
+  let (days, time) = match elapsed.split_once('-') {
+    Some((days, time)) => (days.parse::<u64>().ok()?, time),
+    None => (0, elapsed),
+  };
+  let mut seconds = 0;
+  let mut fields = 0;
+  for field in time.split(':') {
+    seconds = seconds * 60 + field.parse::<u64>().ok()?;
+    fields += 1;
+  }
+  (2..=3).contains(&fields).then_some(days * 86_400 + seconds)
+}
+
+/// Returns whether any process is still attached to the terminal device `tty`.
+#[cfg(unix)]
+fn tty_in_use(tty: &str) -> bool {
+  let name = tty.strip_prefix("/dev/").unwrap_or(tty);
+  Command::new("ps")
+    .args(["-t", name, "-o", "pid="])
+    .output()
+    .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Terminates the process tree of the agent started as `pid`.
+#[tauri::command]
+fn kill_agent_terminal(registry: tauri::State<'_, AgentRegistry>, pid: u32) -> Result<(), AgentError> {
+  kill_agent(&registry, pid)
+}
+
+/// Stops the agent started as `pid` as `stop_agent` does, so a tab tracked
+/// by its tty is stopped even though the launcher's pid is long gone. Only
+/// agents in `registry` can be killed; any other pid, or an agent that has
+/// already exited, fails with `ProcessNotFound`.
+fn kill_agent(registry: &AgentRegistry, pid: u32) -> Result<(), AgentError> {
+  let agent = registry.find_by_pid(pid).ok_or(AgentError::ProcessNotFound(pid))?;
+  if !agent.is_alive() {
+    return Err(AgentError::ProcessNotFound(pid));
+  }
+  stop_agent(&agent)
+}
+
+/// Terminates the process tree rooted at `pid`.
+#[cfg(windows)]
+fn kill_process_tree(pid: u32) -> Result<(), AgentError> {
+  // taskkill exits with 128 when there is no process with that PID
+  const PROCESS_NOT_FOUND: i32 = 128;
+
+  let output = hidden_console(&mut Command::new("taskkill"))
+    .args(["/PID", &pid.to_string(), "/T", "/F"])
+    .output()
+    .map_err(|e| AgentError::KillFailed { pid, reason: e.to_string() })?;
+  match output.status.code() {
+    Some(0) => Ok(()),
+    Some(PROCESS_NOT_FOUND) => Err(AgentError::ProcessNotFound(pid)),
+    _ => Err(AgentError::KillFailed {
+      pid,
+      reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    }),
+  }
+}
+
+/// Sends `signal` (e.g. `-TERM`) to the process tree rooted at `pid`.
+#[cfg(unix)]
+fn signal_process_tree(pid: u32, signal: &str) -> Result<(), AgentError> {
+  // `kill -- -0` would signal the app's own process group
+  if pid == 0 || !process_exists(pid) {
+    return Err(AgentError::ProcessNotFound(pid));
+  }
+
+  // Launched terminals lead their own process group, so signalling the group
+  // reaches the shell and agent too. Fall back to the single process for
+  // PIDs that weren't started that way.
+  let group = format!("-{}", pid);
+  let killed_group = Command::new("kill")
+    .args([signal, "--", &group])
+    .output()
+    .is_ok_and(|output| output.status.success());
+  if killed_group {
+    return Ok(());
+  }
+
+  let output = Command::new("kill")
+    .args([signal, &pid.to_string()])
+    .output()
+    .map_err(|e| AgentError::KillFailed { pid, reason: e.to_string() })?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(AgentError::KillFailed {
+      pid,
+      reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    })
+  }
+}
+
+/// Finds the pane whose shell is `pid` in `tmux list-panes -F
+/// '#{pane_pid} #{pane_id}'` output, and returns its id (`%N`).
+fn tmux_pane_for_pid(listing: &str, pid: u32) -> Option<String> {
+  listing.lines().find_map(|line| {
+    let (owner, pane) = line.trim().split_once(' ')?;
+    (owner.parse::<u32>().ok()? == pid).then(|| pane.to_string())
+  })
+}
+
+/// Types `command` into the tmux pane whose shell is `pid` and presses Enter.
+fn tmux_send_keys(pid: u32, command: &str) -> Result<(), AgentError> {
+  let listing = output_with_timeout(
+    Command::new("tmux").args(["list-panes", "-a", "-F", "#{pane_pid} #{pane_id}"]),
+    DEFAULT_LAUNCH_TIMEOUT_MS,
+  )?;
+  let pane = tmux_pane_for_pid(&String::from_utf8_lossy(&listing.stdout), pid)
+    .ok_or(AgentError::WindowNotFound(pid))?;
+  // -l types the command as text, so words like `Enter` in it aren't read as keys
+  for keys in [&["-l", "--", command][..], &["Enter"]] {
+    let output = output_with_timeout(
+      Command::new("tmux").args(["send-keys", "-t", &pane]).args(keys),
+      DEFAULT_LAUNCH_TIMEOUT_MS,
+    )?;
+    if !output.status.success() {
+      return Err(AgentError::SendFailed(exit_failure("tmux", &output)));
+    }
+  }
+  Ok(())
+}
+
+/// Finds the id of the window owned by `pid` in `wmctrl -lp` output, whose
+/// columns are window id, desktop, PID, host and title.
+#[cfg(any(target_os = "linux", test))]
+fn wmctrl_window_for_pid(listing: &str, pid: u32) -> Option<String> {
+  listing.lines().find_map(|line| {
+    let mut columns = line.split_whitespace();
+    let id = columns.next()?;
+    let owner: u32 = columns.nth(1)?.parse().ok()?;
+    (owner == pid).then(|| id.to_string())
+  })
+}
+
+/// Finds the id of the window titled `title` in `wmctrl -lp` output.
+#[cfg(any(target_os = "linux", test))]
+fn wmctrl_window_for_title(listing: &str, title: &str) -> Option<String> {
+  listing.lines().find_map(|line| {
+    let id = line.split_whitespace().next()?;
+    // The title is whatever follows the first four columns, spaces and all
+    let mut rest = line.trim_start();
+    for _ in 0..4 {
+      rest = rest.split_once(char::is_whitespace)?.1.trim_start();
+    }
+    (rest.trim_end() == title).then(|| id.to_string())
+  })
+}
+
+/// Escapes `text` for matching literally in a POSIX extended regex, which is
+/// what `xdotool search` takes.
+#[cfg(any(target_os = "linux", test))]
+fn ere_escape(text: &str) -> String {
+  text.chars().fold(String::new(), |mut escaped, c| {
+    if ".[]{}()\\*+?^$|".contains(c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+    escaped
+  })
+}
+
+/// Whether an agent launched in `terminal` gets a window owned by some other
+/// process than the one launched, so its pid can't find it. gnome-terminal's
+/// windows all belong to its server, and konsole and terminator put a
+/// `new_tab` in a window that was already open.
+#[cfg(any(target_os = "linux", test))]
+fn window_owned_elsewhere(terminal: &str, new_tab: bool) -> bool {
+  match Path::new(terminal).file_name().and_then(|n| n.to_str()).unwrap_or(terminal) {
+    "gnome-terminal" => true,
+    "konsole" | "terminator" => new_tab,
+    _ => false,
+  }
+}
+
+/// PowerShell that restores and raises the main window of `pid`, exiting
+/// non-zero when the process has no window to raise.
+#[cfg(any(target_os = "windows", test))]
+fn set_foreground_window_script(pid: u32) -> String {
+  [
+    format!("$p = Get-Process -Id {} -ErrorAction SilentlyContinue", pid),
+    "if (-not $p -or $p.MainWindowHandle -eq 0) { exit 1 }".to_string(),
+    "Add-Type -Namespace Native -Name Win -MemberDefinition '\
+     [DllImport(\"user32.dll\")] public static extern bool SetForegroundWindow(IntPtr h); \
+     [DllImport(\"user32.dll\")] public static extern bool ShowWindow(IntPtr h, int n);'"
+      .to_string(),
+    "[Native.Win]::ShowWindow($p.MainWindowHandle, 9) | Out-Null".to_string(),
+    "if (-not [Native.Win]::SetForegroundWindow($p.MainWindowHandle)) { exit 1 }".to_string(),
+  ]
+  .join("; ")
+}
+
+/// Stops whatever is left of `agent`: the processes on its tab's tty when it
+/// has one, or the process tree it was launched as.
+fn stop_agent(agent: &registry::AgentHandle) -> Result<(), AgentError> {
+  #[cfg(unix)]
+  {
+    signal_agent(agent, "-TERM")
+  }
+
+  #[cfg(windows)]
+  match kill_process_tree(agent.info.pid) {
+    Err(AgentError::ProcessNotFound(_)) => Ok(()),
+    result => result,
+  }
+}
+
+/// Sends `signal` to everything running for `agent`. An agent that has
+/// already exited is not an error.
+#[cfg(unix)]
+fn signal_agent(agent: &registry::AgentHandle, signal: &str) -> Result<(), AgentError> {
+  if let Some(tty) = &agent.tty {
+    let name = tty.strip_prefix("/dev/").unwrap_or(tty);
+    let output = Command::new("pkill")
+      .args([signal, "-t", name])
+      .output()
+      .map_err(|e| AgentError::KillFailed { pid: agent.info.pid, reason: e.to_string() })?;
+    // pkill exits with 1 when nothing matched, which means it's already gone
+    return match output.status.code() {
+      Some(0) | Some(1) => Ok(()),
+      _ => Err(AgentError::KillFailed {
+        pid: agent.info.pid,
+        reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+      }),
+    };
+  }
+
+  match signal_process_tree(agent.info.pid, signal) {
+    Err(AgentError::ProcessNotFound(_)) => Ok(()),
+    result => result,
+  }
+}
+
+/// How long agents get to exit on their own after SIGTERM when the app quits.
+#[cfg(unix)]
+const SHUTDOWN_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Stops every agent that is still running, for when the app quits. On Unix
+/// agents are sent SIGTERM and whatever is left after the grace period gets
+/// SIGKILL.
+fn shutdown_agents(registry: &AgentRegistry) {
+  let running: Vec<_> = registry.handles().into_iter().filter(|agent| agent.is_alive()).collect();
+  for agent in &running {
+    if let Err(e) = stop_agent(agent) {
+      warn!("Could not stop {}: {}", agent.info.id, e);
+    }
+  }
+
+  #[cfg(unix)]
+  {
+    let deadline = std::time::Instant::now() + SHUTDOWN_GRACE_PERIOD;
+    let mut remaining = running;
+    while !remaining.is_empty() && std::time::Instant::now() < deadline {
+      thread::sleep(std::time::Duration::from_millis(100));
+      remaining.retain(|agent| agent.is_alive());
+    }
+    for agent in remaining.iter().filter(|agent| agent.is_alive()) {
+      if let Err(e) = signal_agent(agent, "-KILL") {
+        warn!("Could not kill {}: {}", agent.info.id, e);
+      }
+    }
+  }
+}
+
+/// How long `restart_agent` waits for the agent it stopped to exit.
+const RESTART_EXIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Polls until `agent` has exited, for at most `timeout`, and returns whether
+/// it did.
+fn wait_for_exit(agent: &registry::AgentHandle, timeout: std::time::Duration) -> bool {
+  let deadline = std::time::Instant::now() + timeout;
+  while agent.is_alive() {
+    if std::time::Instant::now() >= deadline {
+      return false;
+    }
+    thread::sleep(std::time::Duration::from_millis(50));
+  }
+  true
+}
+
+/// Stops an agent whose terminal is still in its startup delay, before the
+/// command runs, and forgets it. Fails with `AgentAlreadyStarted` once the
+/// delay may have passed; use `kill_agent_terminal` then.
+#[tauri::command(async)]
+fn cancel_pending_launch(registry: tauri::State<'_, AgentRegistry>, id: String) -> Result<(), AgentError> {
+  let agent = registry.get(&id).ok_or_else(|| AgentError::AgentNotFound(id.clone()))?;
+  if !agent.startup_pending() {
+    return Err(AgentError::AgentAlreadyStarted(id));
+  }
+  stop_agent(&agent)?;
+  registry.remove(&id);
+  Ok(())
+}
+
+/// Relaunches a registered agent with the spec it was started with, stopping
+/// it first if it is still running. The relaunched agent gets a new id,
+/// unless the caller chose the old one, which it then keeps.
+///
+/// The new agent only starts once the old one has exited, so the two never
+/// share the working directory or count twice against the agent limit. One
+/// still running after `RESTART_EXIT_TIMEOUT` fails with `KillFailed`.
+///
+/// `env` is added to the agent's environment. An agent picked up from an
+/// earlier run of the app lost the values of its credentials, which fails
+/// with `MissingEnv` unless they are given here.
+#[tauri::command(async)]
+fn restart_agent(
+  app: tauri::AppHandle,
+  registry: tauri::State<'_, AgentRegistry>,
+  config: tauri::State<'_, TerminalConfig>,
+  id: String,
+  env: Option<HashMap<String, String>>,
+) -> Result<AgentInfo, AgentError> {
+  let mut agent = registry.get(&id).ok_or_else(|| AgentError::AgentNotFound(id.clone()))?;
+  let env = env.unwrap_or_default();
+  let missing: Vec<String> =
+    agent.redacted_env.iter().filter(|name| !env.contains_key(*name)).cloned().collect();
+  if !missing.is_empty() {
+    return Err(AgentError::MissingEnv(missing));
+  }
+  if !env.is_empty() {
+    agent.spec.env.get_or_insert_with(HashMap::new).extend(env);
+    agent.redacted_env.clear();
+  }
+  if agent.is_alive() {
+    stop_agent(&agent)?;
+    if !wait_for_exit(&agent, RESTART_EXIT_TIMEOUT) {
+      let reason = format!("still running {} s after being stopped", RESTART_EXIT_TIMEOUT.as_secs());
+      return Err(AgentError::KillFailed { pid: agent.info.pid, reason });
+    }
+  }
+
+  // Relaunching the same command is the point, however soon it comes
+  let spec = AgentSpec { dedupe_window_ms: Some(0), ..agent.spec.clone() };
+  if agent.spec.id.is_some() {
+    // The old entry makes way for the new one, and comes back if it fails
+    registry.remove(&id);
+    return launch_agent(&app, &registry, &config, &spec).inspect_err(|_| registry.restore(agent));
+  }
+  let info = launch_agent(&app, &registry, &config, &spec)?;
+  registry.remove(&id);
+  Ok(info)
+}
+
+/// Runs `command` in a running agent's terminal as if it had been typed
+/// there, e.g. a follow-up once the agent has finished a task. Only tmux
+/// panes, pty sessions and Terminal.app or iTerm tabs can be sent commands;
+/// other terminals fail with `UnscriptableTerminal`.
+#[tauri::command(async)]
+fn send_command_to_agent(
+  registry: tauri::State<'_, AgentRegistry>,
+  sessions: tauri::State<'_, pty::PtySessions>,
+  id: String,
+  command: String,
+) -> Result<(), AgentError> {
+  let command = validate_command(&command)?;
+  // Each line would be run as a command of its own
+  if command.contains(['\n', '\r']) {
+    return Err(AgentError::MultilineCommand);
+  }
+  let agent = registry.get(&id).ok_or_else(|| AgentError::AgentNotFound(id.clone()))?;
+  if !agent.is_alive() {
+    return Err(AgentError::ProcessNotFound(agent.info.pid));
+  }
+  match agent.info.terminal.as_str() {
+    "pty" => return sessions.write(&id, &format!("{}\r", command)),
+    "tmux" => return tmux_send_keys(agent.info.pid, command),
+    _ => {}
+  }
+
+  #[cfg(target_os = "macos")]
+  let mac_terminal = MacTerminal::from_name(&agent.info.terminal);
+  #[cfg(target_os = "macos")]
+  if let (Some(tty), Some(mac_terminal)) = (agent.tty.as_deref(), mac_terminal) {
+    let output = output_with_timeout(
+      Command::new("osascript").args(["-e", &mac_terminal.send_script(tty, command)]),
+      DEFAULT_LAUNCH_TIMEOUT_MS,
+    )?;
+    return match String::from_utf8_lossy(&output.stdout).trim() {
+      "found" if output.status.success() => Ok(()),
+      _ if output.status.success() => Err(AgentError::WindowNotFound(agent.info.pid)),
+      _ => Err(AgentError::SendFailed(exit_failure("osascript", &output))),
+    };
+  }
+
+  Err(AgentError::UnscriptableTerminal(agent.info.terminal.clone()))
+}
+
+/// Brings the terminal window of the agent started as `pid` to the front.
+#[tauri::command(async)]
+fn focus_agent_terminal(registry: tauri::State<'_, AgentRegistry>, pid: u32) -> Result<(), AgentError> {
+  let agent = registry.find_by_pid(pid).ok_or(AgentError::ProcessNotFound(pid))?;
+
+  #[cfg(target_os = "macos")]
+  {
+    // Tabs are matched by tty since the osascript PID is long gone
+    let tty = agent.tty.as_deref().ok_or(AgentError::WindowNotFound(pid))?;
+    let mac_terminal = MacTerminal::from_name(&agent.info.terminal).ok_or(AgentError::WindowNotFound(pid))?;
+    let output = output_with_timeout(
+      Command::new("osascript").args(["-e", &mac_terminal.focus_script(tty)]),
+      DEFAULT_LAUNCH_TIMEOUT_MS,
+    )?;
+    if output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "found" {
+      Ok(())
+    } else {
+      Err(AgentError::WindowNotFound(pid))
+    }
+  }
+
+  #[cfg(target_os = "windows")]
+  {
+    let _ = agent;
+    let output = output_with_timeout(
+      hidden_console(&mut Command::new("powershell"))
+        .args(["-NoProfile", "-Command", &set_foreground_window_script(pid)]),
+      DEFAULT_LAUNCH_TIMEOUT_MS,
+    )?;
+    if output.status.success() {
+      Ok(())
+    } else {
+      Err(AgentError::WindowNotFound(pid))
+    }
+  }
+
+  // Windows drawn by another process can only be told apart by their title
+  #[cfg(target_os = "linux")]
+  {
+    let elsewhere = window_owned_elsewhere(&agent.info.terminal, agent.spec.reuse_window());
+    if !elsewhere && raise_linux_window(LinuxWindow::Pid(pid))? {
+      return Ok(());
+    }
+    match agent.info.title.as_deref() {
+      Some(title) if raise_linux_window(LinuxWindow::Title(title))? => Ok(()),
+      None if elsewhere => Err(AgentError::UnscriptableTerminal(agent.info.terminal.clone())),
+      _ => Err(AgentError::WindowNotFound(pid)),
+    }
+  }
+}
+
+/// How `raise_linux_window` picks the window.
+#[cfg(target_os = "linux")]
+enum LinuxWindow<'a> {
+  /// The window owned by this process.
+  Pid(u32),
+  /// The window with exactly this title.
+  Title(&'a str),
+}
+
+/// Brings `window` to the front with wmctrl or else xdotool, returning
+/// whether either found it.
+#[cfg(target_os = "linux")]
+fn raise_linux_window(window: LinuxWindow<'_>) -> Result<bool, AgentError> {
+  if find_on_path("wmctrl").is_some() {
+    let listing = output_with_timeout(Command::new("wmctrl").arg("-lp"), DEFAULT_LAUNCH_TIMEOUT_MS)?;
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    let found = match window {
+      LinuxWindow::Pid(pid) => wmctrl_window_for_pid(&listing, pid),
+      LinuxWindow::Title(title) => wmctrl_window_for_title(&listing, title),
+    };
+    if let Some(id) = found {
+      let raised =
+        output_with_timeout(Command::new("wmctrl").args(["-i", "-a", &id]), DEFAULT_LAUNCH_TIMEOUT_MS)?;
+      if raised.status.success() {
+        return Ok(true);
+      }
+    }
+  }
+  if find_on_path("xdotool").is_some() {
+    let search = match window {
+      LinuxWindow::Pid(pid) => ["--pid".to_string(), pid.to_string()],
+      LinuxWindow::Title(title) => ["--name".to_string(), format!("^{}$", ere_escape(title))],
+    };
+    let search =
+      output_with_timeout(Command::new("xdotool").arg("search").args(search), DEFAULT_LAUNCH_TIMEOUT_MS)?;
+    if let Some(id) = String::from_utf8_lossy(&search.stdout).lines().next() {
+      let raised = output_with_timeout(
+        Command::new("xdotool").args(["windowactivate", id.trim()]),
+        DEFAULT_LAUNCH_TIMEOUT_MS,
+      )?;
+      if raised.status.success() {
+        return Ok(true);
+      }
+    }
+  }
+  Ok(false)
+}
+
+fn main() {
+  // RUST_LOG picks what is logged, e.g. `RUST_LOG=tasks=debug` to follow
+  // every terminal that is tried. `trace` adds whole command lines.
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(concat!(
+    env!("CARGO_CRATE_NAME"),
+    "=info"
+  )))
+  .init();
+
+  tauri::Builder::default()
+    .setup(|app| {
+      // Agents launched before the app last quit or crashed are picked up
+      // again if they are still running
+      let registry = match app.path_resolver().app_data_dir() {
+        Some(dir) => AgentRegistry::load(dir.join("agents.json")),
+        None => AgentRegistry::default(),
+      };
+      let restored = registry.handles();
+      app.manage(registry);
+      // They aren't the app's children any more, so they are polled for
+      // exiting, which reports no exit code
+      for handle in restored {
+        let launched = LaunchedTerminal {
+          pid: handle.info.pid,
+          terminal: handle.info.terminal,
+          tty: handle.tty,
+          child: None,
+        };
+        let (app, id) = (app.handle(), handle.info.id);
+        watch_agent(launched, move |exit_code| report_exit(&app, id, exit_code));
+      }
+      Ok(())
+    })
+    .manage(config::load_terminal_config())
+    .manage(pty::PtySessions::default())
+    .invoke_handler(tauri::generate_handler![
+      launch_agent_terminal,
+      launch_agent_terminal_args,
+      launch_agent_script,
+      launch_agents,
+      launch_agent_group,
+      kill_group,
+      list_groups,
+      kill_agent_terminal,
+      focus_agent_terminal,
+      send_command_to_agent,
+      restart_agent,
+      cancel_pending_launch,
+      preview_launch_command,
+      resolve_terminal,
+      send_agent_input,
+      resize_agent,
+      list_active_agents,
+      agent_status,
+      detect_available_terminals,
+      diagnostics
+    ])
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app, event| {
+      // Exit is emitted however the app is quit, including when the last
+      // window closes and nothing prevents the ExitRequested before it
+      if let tauri::RunEvent::Exit = event {
+        if !app.state::<TerminalConfig>().keep_agents_on_exit {
+          shutdown_agents(&app.state::<AgentRegistry>());
+        }
+      }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Extracts the string literal passed to `do script` and undoes the
+  /// AppleScript escaping, failing if the literal is not properly terminated.
+  fn parse_do_script_literal(script: &str) -> String {
+    let mut lines = script.lines();
+    assert_eq!(lines.next(), Some("tell application \"Terminal\""));
+    let line = lines.next().expect("missing do script line");
+    let body = line
+      .strip_prefix("  set newTab to do script \"")
+      .expect("unexpected do script line");
+    assert_eq!(lines.collect::<Vec<_>>(), ["  tty of newTab", "end tell"]);
+
+    let mut chars = body.chars();
+    let mut literal = String::new();
+    while let Some(c) = chars.next() {
+      match c {
+        '\\' => literal.push(chars.next().expect("dangling escape")),
+        '"' => {
+          assert_eq!(chars.as_str(), "", "literal terminated early");
+          return literal;
+        }
+        _ => literal.push(c),
+      }
+    }
+    panic!("unterminated string literal in {script}");
+  }
+
+  #[test]
+  fn escape_for_applescript_escapes_quotes_and_backslashes() {
+    assert_eq!(escape_for_applescript(r#"say "hi""#), r#"say \"hi\""#);
+    assert_eq!(escape_for_applescript(r"C:\dir"), r"C:\\dir");
+    assert_eq!(escape_for_applescript("a; b && $HOME"), "a; b && $HOME");
+  }
+
+  #[test]
+  fn shell_quote_wraps_and_escapes_single_quotes() {
+    assert_eq!(shell_quote("/Users/me/project"), "'/Users/me/project'");
+    assert_eq!(shell_quote("/tmp/my dir"), "'/tmp/my dir'");
+    assert_eq!(shell_quote("it's"), r"'it'\''s'");
+  }
+
+  #[test]
+  fn agent_spec_only_requires_command() {
+    let spec: AgentSpec = serde_json::from_str(r#"{ "command": "claude", "title": "Planner" }"#).unwrap();
+    assert_eq!(spec.command, "claude");
+    assert_eq!(spec.title.as_deref(), Some("Planner"));
+    assert!(spec.working_directory.is_none());
+  }
+
+  #[test]
+  fn validate_command_rejects_blank_input() {
+    assert_eq!(validate_command(""), Err(AgentError::EmptyCommand));
+    assert_eq!(validate_command("  \t \n"), Err(AgentError::EmptyCommand));
+    assert_eq!(validate_command("  claude --help  "), Ok("claude --help"));
+  }
+
+  #[test]
+  fn commands_with_line_breaks_are_refused() {
+    let spec = |command: &str| AgentSpec { command: command.to_string(), ..AgentSpec::default() };
+    assert!(matches!(plan_launch(&spec("ls\nrm -rf ~")), Err(AgentError::MultilineCommand)));
+    assert!(matches!(plan_launch(&spec("ls\r\nrm -rf ~")), Err(AgentError::MultilineCommand)));
+    let args = AgentSpec { args: Some(vec!["-c".to_string(), "ls\rrm".to_string()]), ..spec("sh") };
+    assert!(matches!(plan_launch(&args), Err(AgentError::MultilineCommand)));
+    // A trailing newline is trimmed along with other surrounding whitespace
+    assert_eq!(guard_newlines(spec("claude")).unwrap().command, "claude");
+
+    let escaped = AgentSpec { escape_newlines: Some(true), ..spec("ls\nrm -rf ~") };
+    assert_eq!(guard_newlines(escaped).unwrap().command, "ls rm -rf ~");
+    let escaped = AgentSpec { escape_newlines: Some(true), ..args };
+    assert_eq!(guard_newlines(escaped).unwrap().args.unwrap(), ["-c", "ls rm"]);
+  }
+
+  #[test]
+  fn overlong_commands_are_refused() {
+    let prompt = format!("claude -p {}", "x".repeat(DEFAULT_MAX_COMMAND_LENGTH));
+    let spec = AgentSpec { command: prompt, ..AgentSpec::default() };
+    let error = plan_launch(&spec).err().unwrap();
+    assert_eq!(error, AgentError::CommandTooLong { length: DEFAULT_MAX_COMMAND_LENGTH + 10, limit: 100_000 });
+    let short = AgentSpec { max_command_length: Some(5), ..AgentSpec::default() };
+    let args = AgentSpec { args: Some(vec!["it's".to_string()]), ..short.clone() };
+    let error = plan_launch(&AgentSpec { command: "ls".to_string(), ..args }).err().unwrap();
+    // Measured as quoted for the shell: `ls 'it'\''s'`
+    assert_eq!(error, AgentError::CommandTooLong { length: 12, limit: 5 });
+  }
+
+  #[test]
+  fn long_commands_run_from_a_script_that_removes_itself() {
+    let (contents, command) = ScriptShell::Cmd.script("echo 100% \"done\"", r"C:\Temp\agent 1.cmd");
+    assert_eq!(contents, "@echo 100%% \"done\"\r\n@(goto) 2>nul & del \"%~f0\"\r\n");
+    assert_eq!(command, r#"call "C:\Temp\agent 1.cmd""#);
+
+    let (contents, command) = ScriptShell::PowerShell.script("claude -p 'hi'", r"C:\Temp\a.ps1");
+    assert_eq!(contents, "claude -p 'hi'");
+    assert!(command.starts_with(r"$agentScript = Get-Content -Raw -LiteralPath 'C:\Temp\a.ps1'; Remove-Item"));
+    assert!(command.ends_with("a.ps1'; . ([scriptblock]::Create($agentScript))"));
+
+    let file = std::env::temp_dir().join(format!("agentic-sync-script-{}.sh", std::process::id()));
+    let (contents, command) = ScriptShell::Posix.script("echo \"$0\" ran", &file.to_string_lossy());
+    assert_eq!(command, format!(". {}", shell_quote(&file.to_string_lossy())));
+    #[cfg(unix)]
+    {
+      std::fs::write(&file, contents).unwrap();
+      let output = Command::new("sh").args(["-c", &command]).output().unwrap();
+      assert_eq!(String::from_utf8_lossy(&output.stdout), "sh ran\n");
+      assert!(!file.exists());
+    }
+    #[cfg(not(unix))]
+    assert!(contents.starts_with("rm -f "));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn script_spec_runs_executable_scripts_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agentic-sync-scripts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let script = dir.join("run agent.sh");
+    std::fs::write(&script, "#!/bin/sh\nclaude\n").unwrap();
+    let in_dir =
+      AgentSpec { working_directory: Some(dir.to_string_lossy().into_owned()), ..AgentSpec::default() };
+
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let error = script_spec(&in_dir, "run agent.sh").err().unwrap();
+    assert_eq!(error, AgentError::ScriptNotExecutable(script.to_string_lossy().into_owned()));
+
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let spec = script_spec(&in_dir, "run agent.sh").unwrap();
+    assert_eq!(spec.command, script.to_string_lossy());
+    assert_eq!(spec.args, Some(Vec::new()));
+    assert_eq!(spec.command_line(shell_word), shell_quote(&script.to_string_lossy()));
+    // An absolute path doesn't depend on the working directory
+    let absolute = script_spec(&AgentSpec::default(), &script.to_string_lossy()).unwrap();
+    assert_eq!(absolute.command, spec.command);
+
+    let missing = script_spec(&in_dir, "nope.sh").err().unwrap();
+    assert_eq!(missing, AgentError::ScriptNotFound("nope.sh".to_string()));
+    let folder = script_spec(&AgentSpec::default(), &dir.to_string_lossy()).err().unwrap();
+    assert!(matches!(folder, AgentError::ScriptNotFound(_)));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  /// Checks that the line break in an option of `spec` is refused, and
+  /// returns the spec with it turned into a space by `escape_newlines`.
+  fn guarded(spec: AgentSpec, option: &str) -> AgentSpec {
+    assert_eq!(guard_newlines(spec.clone()).err(), Some(AgentError::MultilineOption(option.to_string())));
+    guard_newlines(AgentSpec { escape_newlines: Some(true), ..spec }).unwrap()
+  }
+
+  fn claude() -> AgentSpec {
+    AgentSpec { command: "claude".to_string(), ..AgentSpec::default() }
+  }
+
+  #[test]
+  fn line_breaks_in_the_title_are_refused() {
+    let spec = guarded(AgentSpec { title: Some("a\r\nrm -rf ~".to_string()), ..claude() }, "title");
+    assert_eq!(spec.title.as_deref(), Some("a rm -rf ~"));
+  }
+
+  #[test]
+  fn line_breaks_in_on_complete_are_refused() {
+    let run = AgentSpec { on_complete: Some(OnComplete::Run("echo\nrm -rf ~".to_string())), ..claude() };
+    assert_eq!(guarded(run, "on_complete").on_complete, Some(OnComplete::Run("echo rm -rf ~".to_string())));
+    let shell = OnComplete::Interactive(Some("zsh\nid".to_string()));
+    let spec = guarded(AgentSpec { on_complete: Some(shell), ..claude() }, "on_complete");
+    assert_eq!(spec.on_complete, Some(OnComplete::Interactive(Some("zsh id".to_string()))));
+  }
+
+  #[test]
+  fn line_breaks_in_extra_terminal_args_are_refused() {
+    let spec = AgentSpec { extra_terminal_args: Some(vec!["--x\nid".to_string()]), ..claude() };
+    assert_eq!(guarded(spec, "extra_terminal_args").extra_terminal_args.unwrap(), ["--x id"]);
+  }
+
+  #[test]
+  fn line_breaks_in_the_remote_working_directory_are_refused() {
+    let spec = AgentSpec { remote_working_directory: Some("/srv\nid".to_string()), ..claude() };
+    assert_eq!(guarded(spec, "remote_working_directory").remote_working_directory.as_deref(), Some("/srv id"));
+  }
+
+  #[test]
+  fn line_breaks_in_the_ssh_user_are_refused() {
+    let spec = AgentSpec { ssh_user: Some("me\nid".to_string()), ..claude() };
+    assert_eq!(guarded(spec, "ssh_user").ssh_user.as_deref(), Some("me id"));
+  }
+
+  #[test]
+  fn line_breaks_in_activation_names_are_refused() {
+    for activation in [
+      ActivationSpec::Venv(".venv\nid".to_string()),
+      ActivationSpec::Conda("ml\nid".to_string()),
+      ActivationSpec::Nix(Some(".#dev\nid".to_string())),
+    ] {
+      let spec = guarded(AgentSpec { activate: Some(activation), ..claude() }, "activate");
+      let Some(ActivationSpec::Venv(name) | ActivationSpec::Conda(name) | ActivationSpec::Nix(Some(name))) =
+        spec.activate
+      else {
+        panic!("activation went missing");
+      };
+      assert!(name.ends_with(" id"), "{:?}", name);
+    }
+  }
+
+  #[test]
+  fn line_breaks_in_the_script_path_are_refused() {
+    let error = script_spec(&AgentSpec::default(), "run.sh\nrm -rf ~").err();
+    assert_eq!(error, Some(AgentError::MultilineOption("script_path".to_string())));
+  }
+
+  #[test]
+  fn command_program_finds_the_first_real_word() {
+    let program = |command: &str| {
+      command_program(&AgentSpec { command: command.to_string(), ..AgentSpec::default() })
+    };
+    assert_eq!(program("claude --resume").as_deref(), Some("claude"));
+    assert_eq!(program("FOO=1 BAR=2 npx claude").as_deref(), Some("npx"));
+    assert_eq!(program("'/opt/my tools/claude'"), None);
+    assert_eq!(program("\"./run.sh\" now").as_deref(), Some("./run.sh"));
+    assert_eq!(program("cd /work && claude"), None);
+    assert_eq!(program("$EDITOR notes"), None);
+
+    let spec = AgentSpec {
+      command: "my agent".to_string(),
+      args: Some(vec![]),
+      ..AgentSpec::default()
+    };
+    assert_eq!(command_program(&spec).as_deref(), Some("my agent"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn program_exists_checks_path_and_relative_files() {
+    assert!(program_exists("sh", None, 5_000));
+    assert!(!program_exists("definitely-not-a-real-program-name", None, 5_000));
+    assert!(program_exists("./sh", Some("/bin"), 5_000));
+    assert!(!program_exists("./definitely-not-here", Some("/bin"), 5_000));
+  }
+
+  #[test]
+  fn launch_rejects_missing_program_before_opening_a_terminal() {
+    let result = launch_terminal(&AgentSpec {
+      command: "definitely-not-a-real-program-name --flag".to_string(),
+      ..AgentSpec::default()
+    });
+    assert_eq!(
+      result.unwrap_err(),
+      AgentError::CommandNotFound("definitely-not-a-real-program-name".to_string())
+    );
+  }
+
+  #[test]
+  fn launch_rejects_missing_working_directory() {
+    let result = launch_terminal(&AgentSpec {
+      command: "claude".to_string(),
+      working_directory: Some("/definitely/not/a/real/dir".to_string()),
+      ..AgentSpec::default()
+    });
+    assert_eq!(
+      result.unwrap_err(),
+      AgentError::InvalidWorkingDir("/definitely/not/a/real/dir".to_string())
+    );
+  }
+
+  #[test]
+  fn working_dir_expands_home_and_resolves_relative_paths() {
+    let home = tauri::api::path::home_dir().unwrap().canonicalize().unwrap();
+    assert_eq!(normalize_working_dir("~").unwrap(), home.to_string_lossy());
+    assert_eq!(normalize_working_dir("~/").unwrap(), home.to_string_lossy());
+
+    let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+    assert_eq!(normalize_working_dir(".").unwrap(), cwd.to_string_lossy());
+    assert_eq!(normalize_working_dir("..").unwrap(), cwd.parent().unwrap().to_string_lossy());
+
+    assert_eq!(normalize_working_dir("~nobody"), Err(AgentError::InvalidWorkingDir("~nobody".to_string())));
+    assert!(normalize_working_dir("Cargo.toml").is_err());
+    assert!(normalize_working_dir("no/such/dir").is_err());
+  }
+
+  #[test]
+  fn strip_verbatim_prefix_leaves_plain_windows_paths() {
+    assert_eq!(strip_verbatim_prefix(r"\\?\C:\work"), r"C:\work");
+    assert_eq!(strip_verbatim_prefix(r"\\?\UNC\server\share"), r"\\server\share");
+    assert_eq!(strip_verbatim_prefix("/home/me"), "/home/me");
+  }
+
+  #[test]
+  fn mac_terminal_from_name_accepts_common_spellings() {
+    assert_eq!(MacTerminal::from_name("Terminal"), Some(MacTerminal::Terminal));
+    assert_eq!(MacTerminal::from_name("iTerm2"), Some(MacTerminal::ITerm));
+    assert_eq!(MacTerminal::from_name("iterm"), Some(MacTerminal::ITerm));
+    assert_eq!(MacTerminal::from_name("hyper"), None);
+  }
+
+  #[test]
+  fn iterm_script_writes_command_into_new_window() {
+    let script = MacTerminal::ITerm.script(r#"echo "hi""#, Some("Planner"), None, false, None);
+    assert_eq!(
+      script,
+      concat!(
+        "tell application \"iTerm\"\n",
+        "  activate\n",
+        "  set newWindow to (create window with default profile)\n",
+        "  tell current session of newWindow to write text \"echo \\\"hi\\\"\"\n",
+        "  tell current session of newWindow to set name to \"Planner\"\n",
+        "  tty of current session of newWindow\n",
+        "end tell",
+      )
+    );
+  }
+
+  #[test]
+  fn reuse_window_scripts_open_a_tab_when_a_window_exists() {
+    let script = MacTerminal::ITerm.script("claude", None, None, true, None);
+    assert!(script.contains("    tell newWindow to create tab with default profile\n"));
+    assert!(script.contains("  else\n    set newWindow to (create window with default profile)\n"));
+
+    let script = MacTerminal::Terminal.script("claude", None, None, true, None);
+    assert!(script.contains("keystroke \"t\" using command down"));
+    assert!(script.contains("      set newTab to do script \"claude\" in front window\n"));
+    assert!(script.contains("  if newTab is missing value then\n    set newTab to do script \"claude\"\n"));
+    assert!(script.ends_with("  tty of newTab\nend tell"));
+  }
+
+  #[test]
+  fn terminal_script_sets_custom_title() {
+    let script = MacTerminal::Terminal.script("claude", Some(r#"Agent "1""#), None, false, None);
+    assert_eq!(
+      script,
+      concat!(
+        "tell application \"Terminal\"\n",
+        "  set newTab to do script \"claude\"\n",
+        "  set custom title of newTab to \"Agent \\\"1\\\"\"\n",
+        "  tty of newTab\n",
+        "end tell",
+      )
+    );
+  }
+
+  #[test]
+  fn cmd_title_prefix_escapes_metacharacters() {
+    assert_eq!(cmd_title_prefix(Some("Planner")), "title Planner && ");
+    assert_eq!(cmd_title_prefix(Some("a & b")), "title a ^& b && ");
+    assert_eq!(cmd_title_prefix(None), "");
+  }
+
+  #[test]
+  fn focus_scripts_match_tab_by_tty() {
+    for terminal in [MacTerminal::Terminal, MacTerminal::ITerm] {
+      let script = terminal.focus_script("/dev/ttys003");
+      assert!(script.contains("is \"/dev/ttys003\" then"));
+      assert!(script.contains("return \"found\""));
+      assert_eq!(script.matches("repeat with").count(), script.matches("end repeat").count());
+    }
+  }
+
+  #[test]
+  fn send_scripts_run_the_command_in_the_tab() {
+    let script = MacTerminal::Terminal.send_script("/dev/ttys003", "say \"done\"");
+    let line = "        do script \"say \\\"done\\\"\" in t\n";
+    assert!(script.contains(&format!("if tty of t is \"/dev/ttys003\" then\n{}", line)));
+    let script = MacTerminal::ITerm.send_script("/dev/ttys003", "claude --resume");
+    let line = "          tell s to write text \"claude --resume\"\n";
+    assert!(script.contains(&format!("{}          return \"found\"\n", line)));
+    assert_eq!(script.matches("repeat with").count(), script.matches("end repeat").count());
+  }
+
+  #[test]
+  fn tmux_pane_for_pid_reads_pane_ids() {
+    let listing = "4242 %0\n777 %12\n";
+    assert_eq!(tmux_pane_for_pid(listing, 777).as_deref(), Some("%12"));
+    assert_eq!(tmux_pane_for_pid(listing, 42), None);
+  }
+
+  #[test]
+  fn wmctrl_window_for_pid_reads_pid_column() {
+    let listing = "0x03a00003  0 4242   host Terminal\n0x04c00007 -1 777    host claude — agent\n";
+    assert_eq!(wmctrl_window_for_pid(listing, 777).as_deref(), Some("0x04c00007"));
+    assert_eq!(wmctrl_window_for_pid(listing, 4242).as_deref(), Some("0x03a00003"));
+    assert_eq!(wmctrl_window_for_pid(listing, 1), None);
+  }
+
+  #[test]
+  fn wmctrl_window_for_title_matches_the_whole_title() {
+    let listing = "0x03a00003  0 4242   host Terminal\n0x04c00007 -1 777    host claude — agent\n";
+    assert_eq!(wmctrl_window_for_title(listing, "claude — agent").as_deref(), Some("0x04c00007"));
+    assert_eq!(wmctrl_window_for_title(listing, "Terminal").as_deref(), Some("0x03a00003"));
+    assert_eq!(wmctrl_window_for_title(listing, "claude"), None);
+  }
+
+  #[test]
+  fn ere_escape_matches_titles_literally() {
+    assert_eq!(ere_escape("fix (bug) #3"), "fix \\(bug\\) #3");
+    assert_eq!(ere_escape("a.b*c"), "a\\.b\\*c");
+  }
+
+  #[test]
+  fn window_owned_elsewhere_for_server_terminals_and_tabs() {
+    assert!(window_owned_elsewhere("/usr/bin/gnome-terminal", false));
+    assert!(window_owned_elsewhere("konsole", true));
+    assert!(!window_owned_elsewhere("konsole", false));
+    assert!(!window_owned_elsewhere("xterm", true));
+  }
+
+  #[test]
+  fn set_foreground_window_script_targets_pid() {
+    let script = set_foreground_window_script(4242);
+    assert!(script.starts_with("$p = Get-Process -Id 4242 "));
+    assert!(script.contains("SetForegroundWindow($p.MainWindowHandle)"));
+  }
+
+  #[test]
+  fn posix_runner_picks_flag_per_shell() {
+    assert_eq!(posix_runner("/usr/bin/zsh", false), ["/usr/bin/zsh", "-ic"]);
+    assert_eq!(posix_runner("bash", false), ["bash", "-ic"]);
+    assert_eq!(posix_runner("bash", true), ["bash", "-lc"]);
+    assert_eq!(posix_runner("/bin/dash", true), ["/bin/dash", "-c"]);
+    assert_eq!(posix_runner("/usr/bin/fish", false), ["/bin/sh", "-c"]);
+  }
+
+  #[test]
+  fn agent_spec_shell_prefers_parameter() {
+    let spec = AgentSpec { shell: Some("/usr/bin/fish".to_string()), ..AgentSpec::default() };
+    assert_eq!(spec.shell(), "/usr/bin/fish");
+    assert!(!AgentSpec::default().shell().is_empty());
+  }
+
+  #[test]
+  fn linux_terminal_args_pass_argv_after_the_script() {
+    let argv = ["claude".to_string(), "--model".to_string(), "a b".to_string()];
+    let script = "\"$0\" \"$@\";bash";
+    assert_eq!(
+      linux_terminal_args("xterm", None, None, false, &[], ["bash", "-c"], script, &argv),
+      ["-e", "bash", "-c", script, "claude", "--model", "a b"]
+    );
+    assert_eq!(
+      linux_terminal_args("tilix", None, None, false, &[], ["/usr/bin/zsh", "-ic"], script, &argv),
+      ["-e", "/usr/bin/zsh -ic '\"$0\" \"$@\";bash' 'claude' '--model' 'a b'"]
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn positional_script_runs_argv_without_reinterpreting_it() {
+    let output = Command::new("sh")
+      .args(["-c", "\"$0\" \"$@\"", "printf", "%s|", "a b", "it's", "$HOME;"])
+      .output()
+      .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a b|it's|$HOME;|");
+  }
+
+  #[test]
+  fn command_line_quotes_only_words_that_need_it() {
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      args: Some(vec!["--model".to_string(), "it's big".to_string(), String::new()]),
+      ..AgentSpec::default()
+    };
+    assert_eq!(spec.command_line(shell_word), "claude --model 'it'\\''s big' ''");
+    assert_eq!(spec.command_line(cmd_word), r#"claude --model "it's big" """#);
+
+    let spec = AgentSpec { command: "claude --help | less".to_string(), ..AgentSpec::default() };
+    assert_eq!(spec.command_line(shell_word), "claude --help | less");
+    assert_eq!(cmd_word(r#"C:\my dir\"x""#), r#""C:\my dir\\\"x\"""#);
+    assert_eq!(cmd_word(r"C:\my dir\"), r#""C:\my dir\\""#);
+    assert_eq!(cmd_word(r"C:\work"), r"C:\work");
+  }
+
+  #[test]
+  fn linux_terminal_args_set_title_where_supported() {
+    let script = "claude";
+    let args =
+      |terminal| linux_terminal_args(terminal, Some("Planner"), None, false, &[], ["bash", "-c"], script, &[]);
+    assert_eq!(args("gnome-terminal"), ["--title", "Planner", "--wait", "--", "bash", "-c", script]);
+    assert_eq!(args("xterm"), ["-T", "Planner", "-e", "bash", "-c", script]);
+    assert_eq!(args("konsole"), ["-p", "tabtitle=Planner", "--noclose", "-e", "bash", "-c", script]);
+    assert_eq!(args("tilix"), ["-t", "Planner", "-e", "bash -c 'claude'"]);
+    assert_eq!(args("wezterm"), ["start", "--", "bash", "-c", script]);
+    assert_eq!(args("ghostty"), ["--title=Planner", "-e", "bash", "-c", script]);
+    assert_eq!(args("xdg-terminal-exec"), ["--title=Planner", "--", "bash", "-c", script]);
+  }
+
+  #[test]
+  fn windows_shell_args_for_each_shell() {
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      args: Some(vec!["--prompt".to_string(), "it's done".to_string()]),
+      title: Some("Bob's agent".to_string()),
+      startup_delay_ms: Some(1500),
+      ..AgentSpec::default()
+    };
+    assert_eq!(
+      WindowsShell::Cmd.args(&spec),
+      ["cmd", "/k", "title Bob's agent && timeout /t 2 && claude --prompt \"it's done\""]
+    );
+    let script = "$Host.UI.RawUI.WindowTitle = 'Bob''s agent'; Start-Sleep -Milliseconds 1500; \
+                  & claude --prompt 'it''s done'";
+    assert_eq!(WindowsShell::PowerShell.args(&spec), ["powershell", "-NoExit", "-Command", script]);
+    assert_eq!(WindowsShell::Pwsh.args(&spec)[0], "pwsh");
+
+    let spec = AgentSpec {
+      command: "claude | tee log".to_string(),
+      args: None,
+      startup_delay_ms: Some(0),
+      ..spec
+    };
+    assert_eq!(
+      WindowsShell::Pwsh.args(&spec),
+      ["pwsh", "-NoExit", "-Command", "$Host.UI.RawUI.WindowTitle = 'Bob''s agent'; claude | tee log"]
+    );
+  }
+
+  #[test]
+  fn tee_wrappers_append_output_to_the_log() {
+    assert_eq!(posix_tee("claude", Some("/logs/a b.log")), "{ claude; } 2>&1 | tee -a '/logs/a b.log'");
+    assert_eq!(posix_tee("claude", None), "claude");
+    assert_eq!(
+      cmd_tee("claude", Some(r"C:\logs\a.log")),
+      r#"(claude) 2>&1 | powershell -NoProfile -Command "$input | Tee-Object -FilePath 'C:\logs\a.log' -Append""#
+    );
+    assert_eq!(
+      ps_tee("claude", Some(r"C:\logs\a.log")),
+      r"& { claude } *>&1 | Tee-Object -FilePath 'C:\logs\a.log' -Append"
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn posix_tee_keeps_output_and_writes_log() {
+    let dir = std::env::temp_dir().join(format!("agentic-sync-test-{}", std::process::id()));
+    let log = new_log_path(&dir).unwrap();
+    assert_ne!(log, new_log_path(&dir).unwrap());
+
+    let script = posix_tee("echo out; echo err >&2", Some(&log));
+    let output = Command::new("sh").args(["-c", &script]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\nerr\n");
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "out\nerr\n");
+    let _ = std::fs::remove_dir_all(dir);
+  }
+
+  #[test]
+  fn windows_shell_defaults_to_cmd() {
+    assert_eq!(WindowsShell::for_spec(&AgentSpec::default()), WindowsShell::Cmd);
+    let spec = AgentSpec { shell: Some("PowerShell.exe".to_string()), ..AgentSpec::default() };
+    assert_eq!(WindowsShell::for_spec(&spec), WindowsShell::PowerShell);
+    assert_eq!(WindowsShell::from_name("zsh"), None);
+  }
+
+  #[test]
+  fn windows_terminal_opens_new_tab_and_escapes_semicolons() {
+    let inner = ["cmd".to_string(), "/k".to_string(), "timeout /t 2 && a; b".to_string()];
+    let (program, args) =
+      WindowsTerminal::WindowsTerminal.launch_args(&inner, Some("C:\\work"), Some("Planner"), None, false, &[]);
+    assert_eq!(program, "wt");
+    assert_eq!(
+      args,
+      ["new-tab", "--title", "Planner", "-d", "C:\\work", "cmd", "/k", "timeout /t 2 && a\\; b"]
+    );
+  }
+
+  #[test]
+  fn windows_terminal_reuses_most_recent_window() {
+    let inner = ["cmd".to_string(), "/k".to_string(), "claude".to_string()];
+    let (_, args) = WindowsTerminal::WindowsTerminal.launch_args(&inner, None, None, None, true, &[]);
+    assert_eq!(args, ["-w", "0", "new-tab", "cmd", "/k", "claude"]);
+    let (_, args) = WindowsTerminal::Cmd.launch_args(&inner, None, None, None, true, &[]);
+    assert_eq!(args, ["/C", "start", "cmd", "/k", "claude"]);
+  }
+
+  #[test]
+  fn classic_cmd_uses_start() {
+    let inner = ["cmd".to_string(), "/k".to_string(), "claude".to_string()];
+    let (program, args) = WindowsTerminal::Cmd.launch_args(&inner, None, Some("ignored"), None, false, &[]);
+    assert_eq!(program, "cmd");
+    assert_eq!(args, ["/C", "start", "cmd", "/k", "claude"]);
+    assert_eq!(WindowsTerminal::from_name("wt.exe"), Some(WindowsTerminal::WindowsTerminal));
+  }
+
+  #[test]
+  fn windows_path_to_wsl_maps_drive_letters() {
+    assert_eq!(windows_path_to_wsl(r"C:\Users\me\project"), "/mnt/c/Users/me/project");
+    assert_eq!(windows_path_to_wsl("D:/src"), "/mnt/d/src");
+    assert_eq!(windows_path_to_wsl("/home/me"), "/home/me");
+  }
+
+  #[test]
+  fn wsl_args_run_login_bash_in_translated_directory() {
+    assert_eq!(
+      wsl_args(Some("Ubuntu"), Some(r"C:\work"), 2000, "pwd", Some("bash")),
+      ["wsl.exe", "-d", "Ubuntu", "--", "bash", "-lc", "cd '/mnt/c/work' && sleep 2 && pwd; bash"]
+    );
+    assert_eq!(wsl_args(None, None, 0, "pwd", Some("bash")), ["wsl.exe", "--", "bash", "-lc", "pwd; bash"]);
+    assert_eq!(wsl_args(None, None, 0, "pwd", None), ["wsl.exe", "--", "bash", "-lc", "pwd"]);
+  }
+
+  /// Just the names of `candidates`.
+  fn names(candidates: &[(String, TerminalSource)]) -> Vec<&str> {
+    candidates.iter().map(|(name, _)| name.as_str()).collect()
+  }
+
+  /// A desktop with only `$TERMINAL` set.
+  fn env_terminal(terminal: &str) -> DesktopTerminals {
+    DesktopTerminals { env: Some(terminal.to_string()), ..DesktopTerminals::default() }
+  }
+
+  #[test]
+  fn linux_terminal_candidates_prefer_request_then_env() {
+    let desktop = env_terminal("/usr/bin/alacritty");
+    let candidates = linux_terminal_candidates(Some("kitty"), &[], &desktop, LINUX_TERMINALS);
+    assert_eq!(&names(&candidates)[..3], ["kitty", "/usr/bin/alacritty", "gnome-terminal"]);
+    assert_eq!(names(&candidates).iter().filter(|c| **c == "kitty").count(), 1);
+    let blank = linux_terminal_candidates(None, &[], &env_terminal(""), LINUX_TERMINALS);
+    assert_eq!(blank.len(), LINUX_TERMINALS.len());
+  }
+
+  #[test]
+  fn linux_terminal_candidates_follow_the_preference_list() {
+    let preference = ["ghostty".to_string(), "kitty".to_string(), "gnome-terminal".to_string()];
+    let candidates = linux_terminal_candidates(None, &preference, &env_terminal("xterm"), LINUX_TERMINALS);
+    assert_eq!(&names(&candidates)[..5], ["ghostty", "kitty", "gnome-terminal", "xterm", "konsole"]);
+    assert_eq!(candidates.len(), LINUX_TERMINALS.len());
+    let desktop = DesktopTerminals::default();
+    let candidates = linux_terminal_candidates(Some("tilix"), &preference, &desktop, LINUX_TERMINALS);
+    assert_eq!(&names(&candidates)[..2], ["tilix", "ghostty"]);
+  }
+
+  #[test]
+  fn linux_terminal_candidates_try_env_then_xdg_then_the_system_default() {
+    let desktop = DesktopTerminals {
+      env: Some("alacritty".to_string()),
+      xdg: Some("xdg-terminal-exec".to_string()),
+      system_default: Some("konsole".to_string()),
+    };
+    let candidates = linux_terminal_candidates(None, &[], &desktop, &["gnome-terminal", "konsole"]);
+    assert_eq!(
+      candidates,
+      [
+        ("alacritty".to_string(), TerminalSource::Env),
+        ("xdg-terminal-exec".to_string(), TerminalSource::Xdg),
+        ("konsole".to_string(), TerminalSource::SystemDefault),
+        ("gnome-terminal".to_string(), TerminalSource::Builtin),
+      ]
+    );
+    let desktop = DesktopTerminals { env: None, ..desktop };
+    let candidates = linux_terminal_candidates(Some("kitty"), &["tilix".to_string()], &desktop, &[]);
+    let sources: Vec<TerminalSource> = candidates.iter().map(|(_, source)| *source).collect();
+    let expected = [TerminalSource::Requested, TerminalSource::Preference, TerminalSource::Xdg];
+    assert_eq!(sources, [expected.as_slice(), &[TerminalSource::SystemDefault]].concat());
+  }
+
+  #[test]
+  fn desktop_defaults_are_read_from_gsettings_and_alternatives() {
+    assert_eq!(parse_gsettings_program("'gnome-terminal'\n").as_deref(), Some("gnome-terminal"));
+    assert_eq!(parse_gsettings_program("'kitty --single-instance'").as_deref(), Some("kitty"));
+    assert_eq!(parse_gsettings_program("''\n"), None);
+    assert_eq!(alternative_terminal(Path::new("/no/such/x-terminal-emulator")), "x-terminal-emulator");
+
+    #[cfg(unix)]
+    {
+      let dir = std::env::temp_dir().join(format!("agentic-sync-alternatives-{}", std::process::id()));
+      std::fs::create_dir_all(&dir).unwrap();
+      let alternative = |target: &str| {
+        let link = dir.join("x-terminal-emulator");
+        let _ = std::fs::remove_file(&link);
+        std::fs::write(dir.join(target), "").unwrap();
+        std::os::unix::fs::symlink(dir.join(target), &link).unwrap();
+        alternative_terminal(&link)
+      };
+      assert_eq!(alternative("gnome-terminal.wrapper"), "gnome-terminal");
+      assert_eq!(alternative("kitty"), "kitty");
+      assert_eq!(alternative("koi"), "x-terminal-emulator");
+      let _ = std::fs::remove_dir_all(&dir);
+    }
+  }
+
+  #[test]
+  fn installed_terminals_skips_missing_programs() {
+    let candidates = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let builtin = |names: &[&str]| {
+      candidates(names).into_iter().map(|name| (name, TerminalSource::Builtin)).collect()
+    };
+    let none = PackagedTerminals::default();
+    #[cfg(unix)]
+    {
+      let installed = installed_terminals(builtin(&["no-such-term", "sh", "/bin/sh"]), &none).unwrap();
+      assert_eq!(installed.iter().map(|(launcher, _)| launcher.name()).collect::<Vec<_>>(), ["sh", "/bin/sh"]);
+    }
+
+    let error = installed_terminals(builtin(&["no-such-term", "/no/such/xterm"]), &none).err().unwrap();
+    assert_eq!(error, AgentError::NoTerminalFound(candidates(&["no-such-term", "/no/such/xterm"])));
+    assert_eq!(
+      error.to_string(),
+      "No supported terminal emulator is installed (looked for no-such-term, /no/such/xterm). \
+       Install one of them, or set $TERMINAL to the terminal you use"
+    );
+  }
+
+  #[test]
+  fn resolving_a_terminal_follows_the_preference_list() {
+    let none = PackagedTerminals::default();
+    #[cfg(unix)]
+    {
+      let preference = vec!["no-such-term".to_string(), "sh".to_string()];
+      let spec = AgentSpec { terminal_preference: Some(preference), ..AgentSpec::default() };
+      let launchers = linux_launchers(&spec, &env_terminal("xterm"), LINUX_TERMINALS, &none).unwrap();
+      let resolved = first_terminal(launchers).unwrap();
+      assert_eq!(resolved, ResolvedTerminal { terminal: "sh".to_string(), source: TerminalSource::Preference });
+      let json = serde_json::to_value(&resolved).unwrap();
+      assert_eq!(json, serde_json::json!({ "terminal": "sh", "source": "preference" }));
+    }
+
+    let builtin = ["no-such-gnome-terminal"];
+    let preference = vec!["no-such-term".to_string()];
+    let spec = AgentSpec { terminal_preference: Some(preference), ..AgentSpec::default() };
+    let error = linux_launchers(&spec, &env_terminal("no-such-xterm"), &builtin, &none).err().unwrap();
+    let looked_for = ["no-such-term", "no-such-xterm", "no-such-gnome-terminal"];
+    assert_eq!(error, AgentError::NoTerminalFound(looked_for.map(String::from).to_vec()));
+    assert_eq!(first_terminal(Vec::new()), Err(AgentError::NoTerminalFound(Vec::new())));
+  }
+
+  #[test]
+  fn packaged_terminals_come_from_snap_and_flatpak() {
+    let snaps = "Name      Version  Rev   Tracking       Publisher  Notes\n\
+                 alacritty 0.13.2   113   latest/stable  snapcrafters  classic\n\
+                 core22    20240111 1122  latest/stable  canonical  base\n";
+    let packaged = PackagedTerminals {
+      snaps: parse_snap_list(snaps),
+      flatpaks: vec!["org.gnome.Terminal".to_string(), "org.mozilla.firefox".to_string()],
+    };
+    assert_eq!(packaged.snaps, ["alacritty", "core22"]);
+    assert_eq!(packaged.launcher("alacritty").unwrap().name(), "/snap/bin/alacritty");
+
+    let spec = AgentSpec { command: "claude".to_string(), startup_delay_ms: Some(0), ..AgentSpec::default() };
+    let gnome = packaged.launcher("gnome-terminal").unwrap().build_command(&spec);
+    assert_eq!(gnome.get_program(), "flatpak");
+    assert_eq!(gnome.get_args().take(2).collect::<Vec<_>>(), ["run", "org.gnome.Terminal"]);
+    assert!(packaged.launcher("konsole").is_none());
+    assert!(packaged.launcher("xterm").is_none());
+
+    let candidates =
+      ["no-such-term", "gnome-terminal"].map(|name| (name.to_string(), TerminalSource::Builtin)).to_vec();
+    let installed = installed_terminals(candidates, &packaged).unwrap();
+    assert_eq!(installed.iter().map(|(launcher, _)| launcher.name()).collect::<Vec<_>>(), ["gnome-terminal"]);
+  }
+
+  #[test]
+  fn linux_terminal_args_match_each_emulator() {
+    let script = "sleep 2 && claude;bash";
+    let args = |terminal| linux_terminal_args(terminal, None, None, false, &[], ["bash", "-c"], script, &[]);
+    assert_eq!(args("gnome-terminal"), ["--wait", "--", "bash", "-c", script]);
+    assert_eq!(args("xterm"), ["-e", "bash", "-c", script]);
+    assert_eq!(args("konsole"), ["--noclose", "-e", "bash", "-c", script]);
+    assert_eq!(args("alacritty"), ["-e", "bash", "-c", script]);
+    assert_eq!(args("kitty"), ["bash", "-c", script]);
+    assert_eq!(args("/usr/bin/wezterm"), ["start", "--", "bash", "-c", script]);
+    assert_eq!(args("terminator"), ["-x", "bash", "-c", script]);
+    assert_eq!(args("tilix"), ["-e", "bash -c 'sleep 2 && claude;bash'"]);
+    assert_eq!(args("foot"), ["-e", "bash", "-c", script]);
+    assert_eq!(args("ghostty"), ["-e", "bash", "-c", script]);
+    assert_eq!(args("xdg-terminal-exec"), ["--", "bash", "-c", script]);
+  }
+
+  #[test]
+  fn mac_cli_terminals_run_from_their_app_bundle() {
+    let apps = std::env::temp_dir().join(format!("agentic-sync-apps-{}", std::process::id()));
+    let app_dirs = [apps.clone()];
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      shell: Some("/bin/zsh".to_string()),
+      startup_delay_ms: Some(0),
+      ..AgentSpec::default()
+    };
+    for (program, bundle_binary) in MAC_CLI_TERMINALS {
+      // A name that can't be on PATH, so only the bundle can be found
+      let missing = format!("agentic-sync-no-{}", program);
+      assert_eq!(find_mac_cli_terminal(&missing, bundle_binary, &app_dirs), None);
+
+      let binary = apps.join(bundle_binary);
+      std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+      std::fs::write(&binary, "").unwrap();
+      let found = find_mac_cli_terminal(&missing, bundle_binary, &app_dirs).unwrap();
+      assert_eq!(found, binary.to_string_lossy());
+
+      let command = LinuxTerminal { program: found }.build_command(&spec);
+      let args: Vec<_> = command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+      let shell = ["/bin/zsh", "-ic", "eval \"$0\";/bin/zsh", "claude"];
+      match *program {
+        "kitty" => assert_eq!(args, shell),
+        "ghostty" => assert_eq!(args, [&["-e"][..], &shell].concat()),
+        _ => assert_eq!(args, [&["start", "--"][..], &shell].concat()),
+      }
+    }
+    let _ = std::fs::remove_dir_all(apps);
+  }
+
+  #[test]
+  fn profile_is_passed_where_the_terminal_has_profiles() {
+    let args =
+      |terminal| linux_terminal_args(terminal, None, Some("Agents"), false, &[], ["bash", "-c"], "claude", &[]);
+    assert_eq!(args("gnome-terminal"), ["--profile=Agents", "--wait", "--", "bash", "-c", "claude"]);
+    assert_eq!(args("konsole"), ["--profile", "Agents", "--noclose", "-e", "bash", "-c", "claude"]);
+    assert_eq!(args("tilix")[0], "--profile=Agents");
+    assert_eq!(args("xterm"), ["-e", "bash", "-c", "claude"]);
+
+    let inner = ["cmd".to_string()];
+    let (_, args) =
+      WindowsTerminal::WindowsTerminal.launch_args(&inner, None, None, Some("Agents"), false, &[]);
+    assert_eq!(args, ["new-tab", "--profile", "Agents", "cmd"]);
+
+    let script = MacTerminal::ITerm.script("claude", None, Some("Agents"), true, None);
+    assert!(script.contains("    tell newWindow to create tab with profile \"Agents\"\n"));
+    assert!(script.contains("    set newWindow to (create window with profile \"Agents\")\n"));
+    let script = MacTerminal::Terminal.script("claude", None, Some("Pro"), false, None);
+    assert!(script.contains("\n  set current settings of newTab to settings set \"Pro\"\n"));
+  }
+
+  #[test]
+  fn geometry_flags_for_each_terminal() {
+    let position = Some(WindowPosition { x: 10, y: 20 });
+    let spec = AgentSpec { cols: Some(100), position, ..AgentSpec::default() };
+    let geometry = spec.geometry();
+    assert_eq!(geometry.unwrap().size, Some((100, 24)));
+    assert_eq!(linux_geometry_args("xterm", geometry), ["-geometry", "100x24+10+20"]);
+    assert_eq!(linux_geometry_args("/usr/bin/gnome-terminal", geometry), ["--geometry=100x24+10+20"]);
+    assert!(linux_geometry_args("kitty", geometry).is_empty());
+    assert_eq!(wt_geometry_args(geometry), ["--size", "100,24", "--pos", "10,20"]);
+    assert!(AgentSpec::default().geometry().is_none());
+    assert!(wt_geometry_args(None).is_empty());
+
+    let position_only = AgentSpec { position: Some(WindowPosition { x: 0, y: 5 }), ..AgentSpec::default() };
+    assert_eq!(linux_geometry_args("xterm", position_only.geometry()), ["-geometry", "+0+5"]);
+
+    let script = MacTerminal::ITerm.script("claude", None, None, false, geometry);
+    assert!(script.contains("\n  tell current session of newWindow to set columns to 100\n"));
+    assert!(script.contains("\n  set bounds of newWindow to {10, 20, 10 + x2 - x1, 20 + y2 - y1}\n"));
+    let script = MacTerminal::Terminal.script("claude", None, None, false, geometry);
+    assert!(script.contains("\n  set number of rows of newTab to 24\n"));
+    assert!(script.contains("\n  set {x1, y1, x2, y2} to bounds of front window\n"));
+  }
+
+  #[test]
+  fn extra_terminal_args_go_before_the_command() {
+    let extra = ["-hold".to_string(), "-fa".to_string(), "Mono".to_string()];
+    let args =
+      |terminal| linux_terminal_args(terminal, Some("T"), None, false, &extra, ["sh", "-c"], "claude", &[]);
+    assert_eq!(args("xterm"), ["-T", "T", "-hold", "-fa", "Mono", "-e", "sh", "-c", "claude"]);
+    assert_eq!(args("gnome-terminal")[2..6], ["-hold", "-fa", "Mono", "--wait"]);
+    assert_eq!(args("wezterm")[..4], ["-hold", "-fa", "Mono", "start"]);
+
+    let inner = ["cmd".to_string()];
+    let (_, args) = WindowsTerminal::WindowsTerminal.launch_args(&inner, None, None, None, true, &extra[..1]);
+    assert_eq!(args, ["-w", "0", "-hold", "new-tab", "cmd"]);
+    let (_, args) = WindowsTerminal::Cmd.launch_args(&inner, None, None, None, false, &["/max".to_string()]);
+    assert_eq!(args, ["/C", "start", "/max", "cmd"]);
+  }
+
+  #[test]
+  fn extra_terminal_args_must_not_repeat_the_command() {
+    let spec = |extra: &[&str]| AgentSpec {
+      command: "claude --resume".to_string(),
+      extra_terminal_args: Some(extra.iter().map(|arg| arg.to_string()).collect()),
+      ..AgentSpec::default()
+    };
+    assert!(validate_extra_terminal_args(&spec(&["-hold"])).is_ok());
+    assert_eq!(
+      validate_extra_terminal_args(&spec(&["-hold", "claude"])),
+      Err(AgentError::InvalidTerminalArgs("claude".to_string()))
+    );
+    assert!(validate_extra_terminal_args(&spec(&["claude --resume"])).is_err());
+  }
+
+  #[test]
+  fn linux_terminal_args_ask_for_a_tab_where_supported() {
+    let script = "claude;bash";
+    let args = |terminal| linux_terminal_args(terminal, None, None, true, &[], ["bash", "-c"], script, &[]);
+    assert_eq!(args("gnome-terminal"), ["--tab", "--wait", "--", "bash", "-c", script]);
+    assert_eq!(args("konsole"), ["--new-tab", "--noclose", "-e", "bash", "-c", script]);
+    assert_eq!(args("terminator"), ["--new-tab", "-x", "bash", "-c", script]);
+    assert_eq!(args("xterm"), ["-e", "bash", "-c", script]);
+  }
+
+  #[test]
+  fn delay_prefixes_use_platform_units() {
+    assert_eq!(posix_delay_prefix(2000), "sleep 2 && ");
+    assert_eq!(posix_delay_prefix(1500), "sleep 1.5 && ");
+    assert_eq!(posix_delay_prefix(250), "sleep 0.25 && ");
+    assert_eq!(posix_delay_prefix(0), "");
+    assert_eq!(cmd_delay_prefix(2000), "timeout /t 2 && ");
+    assert_eq!(cmd_delay_prefix(1500), "timeout /t 2 && ");
+    assert_eq!(cmd_delay_prefix(0), "");
+  }
+
+  #[test]
+  fn ssh_spec_runs_the_command_remotely() {
+    let spec = AgentSpec {
+      command: "claude --prompt 'fix it'".to_string(),
+      env: Some(HashMap::from([("MODEL".to_string(), "big".to_string())])),
+      ssh_user: Some("me".to_string()),
+      remote_working_directory: Some("/srv/my repo".to_string()),
+      ..AgentSpec::default()
+    };
+    let remote = ssh_spec(&spec, "build-box").unwrap();
+    assert_eq!(remote.command, "ssh");
+    assert_eq!(
+      remote.args.unwrap(),
+      ["-t", "--", "me@build-box", "cd '/srv/my repo' && export MODEL='big' && claude --prompt 'fix it'"]
+    );
+    assert!(remote.env.is_none());
+    assert!(remote.ssh_host.is_none());
+
+    assert_eq!(ssh_spec(&spec, "build box").err(), Some(AgentError::InvalidSshHost("build box".to_string())));
+  }
+
+  #[test]
+  fn ssh_spec_rejects_users_that_arent_user_names() {
+    for user in ["", "me you", "me\tyou", "me@evil", "-oProxyCommand=id"] {
+      let spec =
+        AgentSpec { command: "claude".to_string(), ssh_user: Some(user.to_string()), ..AgentSpec::default() };
+      assert_eq!(ssh_spec(&spec, "build-box").err(), Some(AgentError::InvalidSshUser(user.to_string())));
+    }
+  }
+
+  #[test]
+  fn activation_prefixes_the_command_for_each_shell() {
+    let args = Some(vec!["fix it".to_string()]);
+    let spec = AgentSpec { command: "claude".to_string(), args, ..AgentSpec::default() };
+    let venv = ActivationSpec::Venv("/work/my env".to_string());
+    let activated = posix_activated_spec(&spec, &venv);
+    assert_eq!(activated.command, ". '/work/my env/bin/activate' && claude 'fix it'");
+    assert_eq!((activated.args, activated.activate), (None, None));
+    let conda = posix_activated_spec(&spec, &ActivationSpec::Conda("ml".to_string()));
+    assert_eq!(conda.command, "eval \"$(conda shell.posix hook)\" && conda activate 'ml' && claude 'fix it'");
+
+    let nix = posix_activated_spec(&spec, &ActivationSpec::Nix(Some(".#dev".to_string())));
+    assert_eq!(nix.command, "nix");
+    assert_eq!(nix.args.unwrap(), ["develop", ".#dev", "-c", "claude", "fix it"]);
+    let line = AgentSpec { command: "claude && echo done".to_string(), ..AgentSpec::default() };
+    let nix = posix_activated_spec(&line, &ActivationSpec::Nix(None));
+    assert_eq!(nix.args.unwrap(), ["develop", "-c", "sh", "-c", "claude && echo done"]);
+
+    let venv = ActivationSpec::Venv("C:\\work\\.venv\\".to_string());
+    let cmd = windows_activated_spec(&spec, &venv, WindowsShell::Cmd).unwrap();
+    assert_eq!(cmd.command, "call C:\\work\\.venv\\Scripts\\activate.bat && claude \"fix it\"");
+    let pwsh = windows_activated_spec(&spec, &venv, WindowsShell::Pwsh).unwrap();
+    assert_eq!(pwsh.command, "& 'C:\\work\\.venv\\Scripts\\Activate.ps1'; & claude 'fix it'");
+    let conda = windows_activated_spec(&line, &ActivationSpec::Conda("ml".to_string()), WindowsShell::Cmd);
+    assert_eq!(conda.unwrap().command, "call conda activate ml && claude && echo done");
+    assert!(windows_activated_spec(&spec, &ActivationSpec::Nix(None), WindowsShell::Cmd).is_err());
+  }
+
+  #[test]
+  fn activating_a_venv_checks_that_it_exists() {
+    let dir = std::env::temp_dir().join(format!("agentic-sync-venv-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join(".venv")).unwrap();
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      working_directory: Some(dir.to_string_lossy().into_owned()),
+      ..AgentSpec::default()
+    };
+    assert!(activate_spec(&spec, &ActivationSpec::Venv(".venv".to_string())).is_ok());
+    let missing = activate_spec(&spec, &ActivationSpec::Venv("no-such-venv".to_string())).err().unwrap();
+    assert_eq!(
+      missing.to_string(),
+      "Can't activate the environment: virtual environment no-such-venv does not exist"
+    );
+    let blank = activate_spec(&spec, &ActivationSpec::Conda(" ".to_string())).err();
+    assert_eq!(blank, Some(AgentError::InvalidEnvironment("no environment was named".to_string())));
+
+    // The venv is on the other machine, so it can't be checked from here
+    let remote = AgentSpec { ssh_host: Some("build-box".to_string()), ..spec };
+    let activated = activate_spec(&remote, &ActivationSpec::Venv("no-such-venv".to_string())).unwrap();
+    assert_eq!(activated.command, ". 'no-such-venv/bin/activate' && claude");
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn venvs_are_found_under_a_home_relative_working_directory() {
+    let name = format!("agentic-sync-venv-{}", std::process::id());
+    let dir = tauri::api::path::home_dir().unwrap().join(&name);
+    std::fs::create_dir_all(dir.join(".venv")).unwrap();
+    let spec = AgentSpec {
+      command: "claude".to_string(),
+      working_directory: Some(format!("~/{}", name)),
+      activate: Some(ActivationSpec::Venv(".venv".to_string())),
+      check_command: Some(false),
+      headless: Some(true),
+      ..AgentSpec::default()
+    };
+    let planned = plan_launch(&spec).map(|(spec, _)| spec);
+    let _ = std::fs::remove_dir_all(&dir);
+    let planned = planned.unwrap();
+    assert!(Path::new(planned.working_directory.as_deref().unwrap()).is_absolute());
+    assert!(planned.activate.is_none());
+  }
+
+  #[test]
+  fn env_export_prefix_quotes_values_in_name_order() {
+    let env = HashMap::from([
+      ("MODEL".to_string(), "it's \"big\"".to_string()),
+      ("API_KEY".to_string(), "a b $c".to_string()),
+    ]);
+    assert_eq!(
+      env_export_prefix(&env),
+      r#"export API_KEY='a b $c' && export MODEL='it'\''s "big"' && "#
+    );
+    assert_eq!(env_export_prefix(&HashMap::new()), "");
+  }
+
+  #[test]
+  fn validate_env_rejects_names_the_shell_cannot_export() {
+    let env = |name: &str| HashMap::from([(name.to_string(), "x".to_string())]);
+    assert!(validate_env(&env("_FOO1")).is_ok());
+    assert_eq!(validate_env(&env("1FOO")), Err(AgentError::InvalidEnvVar("1FOO".to_string())));
+    assert!(validate_env(&env("FOO=bar; rm")).is_err());
+    assert!(validate_env(&env("")).is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn env_export_prefix_is_seen_by_the_shell() {
+    let env = HashMap::from([("FOO".to_string(), "bar baz".to_string())]);
+    let script = format!("{}echo \"$FOO\"", env_export_prefix(&env));
+    let output = Command::new("sh").args(["-c", &script]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "bar baz\n");
+  }
+
+  #[test]
+  fn tmux_args_create_session_or_add_window() {
+    assert_eq!(
+      tmux_args("agents", false, "claude", Some("bash"), Some("/work"), Some("planner")),
+      [
+        "new-session", "-d", "-s", "agents", "-n", "planner", "-c", "/work", "-P", "-F", "#{pane_pid}",
+        "claude; bash",
+      ]
+    );
+    assert_eq!(
+      tmux_args("agents", true, "claude", Some("zsh"), None, None),
+      ["new-window", "-t", "agents:", "-P", "-F", "#{pane_pid}", "claude; zsh"]
+    );
+    assert_eq!(tmux_args("agents", true, "claude", None, None, None).last().unwrap(), "claude");
+  }
+
+  #[test]
+  fn find_on_path_skips_missing_programs() {
+    assert!(find_on_path("definitely-not-a-real-program-name").is_none());
+  }
+
+  #[test]
+  fn programs_on_path_filters_out_missing_binaries() {
+    let none = PackagedTerminals::default();
+    assert!(programs_on_path(&["definitely-not-a-real-program-name"], &none).is_empty());
+    #[cfg(unix)]
+    assert_eq!(programs_on_path(&["definitely-not-a-real-program-name", "sh"], &none), ["sh"]);
+    let snapped = PackagedTerminals { snaps: vec!["kitty".to_string()], ..PackagedTerminals::default() };
+    assert_eq!(programs_on_path(&["kitty", "definitely-not-a-real-program-name"], &snapped), ["kitty"]);
+  }
+
+  #[cfg(unix)]
+  #[cfg(unix)]
+  #[test]
+  fn remove_capture_deletes_files_that_are_still_open() {
+    let path = std::env::temp_dir().join(format!("agentic-sync-capture-{}.stderr", std::process::id()));
+    let open = std::fs::File::create(&path).unwrap();
+    remove_capture(path.clone());
+    assert!(!path.exists());
+    drop(open);
+  }
+
+  #[test]
+  fn spawn_detached_returns_before_long_running_command_exits() {
+    use std::time::{Duration, Instant};
+
+    let started = Instant::now();
+    let mut child = spawn_detached(Command::new("sleep").arg("30")).expect("spawn sleep");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let _ = child.kill();
+    let _ = child.wait();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn output_with_timeout_kills_slow_launchers() {
+    use std::time::{Duration, Instant};
+
+    let started = Instant::now();
+    let result = output_with_timeout(Command::new("sleep").arg("30"), 100);
+    assert_eq!(result.unwrap_err(), AgentError::Timeout(100));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let output = output_with_timeout(Command::new("echo").arg("/dev/ttys003"), 5_000).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "/dev/ttys003\n");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn watch_agent_reports_exit_code() {
+    use std::time::Duration;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let child = spawn_detached(Command::new("sh").args(["-c", "exit 3"])).expect("spawn sh");
+    let launched = LaunchedTerminal {
+      pid: child.id(),
+      terminal: "sh".to_string(),
+      tty: None,
+      child: Some(child),
+    };
+    watch_agent(launched, move |code| sender.send(code).unwrap());
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(Some(3)));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn kill_agent_stops_process_group() {
+    let mut child = spawn_detached(Command::new("sh").args(["-c", "sleep 30; true"])).expect("spawn sh");
+    assert!(process_exists(child.id()));
+    let registry = AgentRegistry::default();
+    let launched = LaunchedTerminal { pid: child.id(), terminal: "sh".to_string(), tty: None, child: None };
+    registry.register(&AgentSpec { command: "sleep 30".to_string(), ..AgentSpec::default() }, &launched);
+    assert_eq!(kill_agent(&registry, child.id()), Ok(()));
+    let _ = child.wait();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn wait_for_exit_waits_for_stopped_agents() {
+    let registry = AgentRegistry::default();
+    let register = |script: &str| {
+      let mut child = spawn_detached(Command::new("sh").args(["-c", script])).expect("spawn sh");
+      let launched = LaunchedTerminal { pid: child.id(), terminal: "sh".to_string(), tty: None, child: None };
+      let spec = AgentSpec { command: script.to_string(), ..AgentSpec::default() };
+      let info = registry.register(&spec, &launched);
+      // Reaped as watch_agent would, or the exited agent lingers as a zombie
+      thread::spawn(move || child.wait());
+      registry.get(&info.id).unwrap()
+    };
+    let agent = register("sleep 30; true");
+    let stubborn = register("trap '' TERM; sleep 30; true");
+    // Give sh a moment to install the trap before it is signalled
+    thread::sleep(std::time::Duration::from_millis(200));
+
+    stop_agent(&agent).unwrap();
+    assert!(wait_for_exit(&agent, std::time::Duration::from_secs(5)));
+    stop_agent(&stubborn).unwrap();
+    assert!(!wait_for_exit(&stubborn, std::time::Duration::from_millis(300)));
+    signal_agent(&stubborn, "-KILL").unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn kill_agent_stops_agents_tracked_by_their_tty() {
+    let mut spawned =
+      pty::spawn(Command::new("sleep").arg("30"), pty::DEFAULT_ROWS, pty::DEFAULT_COLS).expect("spawn sleep");
+    let output = Command::new("ps").args(["-o", "tty=", "-p", &spawned.pid.to_string()]).output().unwrap();
+    let tty = format!("/dev/{}", String::from_utf8_lossy(&output.stdout).trim());
+    // As on macOS, the pid is the launcher's, which has exited already
+    let launcher = i32::MAX as u32;
+    let registry = AgentRegistry::default();
+    let launched =
+      LaunchedTerminal { pid: launcher, terminal: "Terminal".to_string(), tty: Some(tty), child: None };
+    registry.register(&AgentSpec { command: "sleep 30".to_string(), ..AgentSpec::default() }, &launched);
+
+    assert_eq!(kill_agent(&registry, launcher), Ok(()));
+    assert!(!spawned.child.wait().unwrap().success());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn kill_agent_only_kills_registered_agents() {
+    let mut child = spawn_detached(Command::new("sh").args(["-c", "sleep 30; true"])).expect("spawn sh");
+    let registry = AgentRegistry::default();
+    assert_eq!(kill_agent(&registry, child.id()), Err(AgentError::ProcessNotFound(child.id())));
+    assert!(process_exists(child.id()));
+    assert_eq!(signal_process_tree(child.id(), "-TERM"), Ok(()));
+    let _ = child.wait();
+
+    let pid = i32::MAX as u32;
+    assert_eq!(signal_process_tree(pid, "-TERM"), Err(AgentError::ProcessNotFound(pid)));
+    assert_eq!(signal_process_tree(0, "-TERM"), Err(AgentError::ProcessNotFound(0)));
+  }
+
+  #[test]
+  fn parse_elapsed_reads_ps_etime() {
+    assert_eq!(parse_elapsed("00:07"), Some(7));
+    assert_eq!(parse_elapsed("01:02:03"), Some(3723));
+    assert_eq!(parse_elapsed("2-00:00:01"), Some(172_801));
+    assert_eq!(parse_elapsed(""), None);
+    assert_eq!(parse_elapsed("12"), None);
+  }
+
+  #[test]
+  fn describe_command_shows_directory_env_and_quoted_args() {
+    let mut command = Command::new("gnome-terminal");
+    command.args(["--", "bash", "-ic", "claude;bash"]).env("FOO", "a b").current_dir("/my work");
+    assert_eq!(
+      describe_command(&command),
+      "cd '/my work' && FOO='a b' gnome-terminal -- bash -ic 'claude;bash'"
+    );
+  }
+
+  /// Fails to open the given number of times, then opens.
+  struct FlakyTerminal {
+    failures: std::cell::Cell<u32>,
+  }
+
+  impl TerminalLauncher for FlakyTerminal {
+    fn build_command(&self, _spec: &AgentSpec) -> Command {
+      Command::new("true")
+    }
+
+    fn name(&self) -> &str {
+      "flaky"
+    }
+
+    fn launch(&self, _spec: &AgentSpec) -> Result<LaunchedTerminal, AgentError> {
+      match self.failures.get() {
+        0 => Ok(LaunchedTerminal { pid: 1, terminal: "flaky".to_string(), tty: None, child: None }),
+        n => {
+          self.failures.set(n - 1);
+          Err(AgentError::LaunchFailed("cannot open display".to_string()))
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn launch_with_retries_gives_failed_terminals_another_chance() {
+    let spec = AgentSpec { max_retries: Some(2), retry_delay_ms: Some(1), ..AgentSpec::default() };
+    let flaky = FlakyTerminal { failures: 2.into() };
+    assert!(launch_with_retries(&flaky, &spec).is_ok());
+
+    let flaky = FlakyTerminal { failures: 3.into() };
+    assert_eq!(
+      launch_with_retries(&flaky, &spec).unwrap_err(),
+      AgentError::LaunchFailed("cannot open display".to_string())
+    );
+    assert_eq!(flaky.failures.get(), 0);
+  }
+
+  #[test]
+  fn failures_that_may_be_the_agents_own_are_not_retried() {
+    let closing = AgentSpec {
+      max_retries: Some(2),
+      retry_delay_ms: Some(1),
+      startup_delay_ms: Some(0),
+      on_complete: Some(OnComplete::Close),
+      ..AgentSpec::default()
+    };
+    let flaky = FlakyTerminal { failures: 2.into() };
+    assert!(launch_with_retries(&flaky, &closing).is_err());
+    assert_eq!(flaky.failures.get(), 1);
+
+    let no_time = std::time::Duration::ZERO;
+    assert!(!retry_is_safe(&closing, no_time));
+    let run = AgentSpec { on_complete: Some(OnComplete::Run("echo done".to_string())), ..closing.clone() };
+    assert!(!retry_is_safe(&run, no_time));
+    // Still waiting out the delay, or held open by a shell, the agent can't have run
+    let delayed = AgentSpec { startup_delay_ms: Some(1000), ..closing.clone() };
+    assert!(retry_is_safe(&delayed, no_time));
+    assert!(!retry_is_safe(&delayed, std::time::Duration::from_secs(2)));
+    let held = AgentSpec { on_complete: None, keep_open: None, ..closing };
+    assert!(retry_is_safe(&held, no_time));
+  }
+
+  #[test]
+  fn all_terminals_failed_lists_every_attempt() {
+    let error = AgentError::AllTerminalsFailed(vec![
+      ("kitty".to_string(), "kitty exited with exit status: 1".to_string()),
+      ("xterm".to_string(), "No such file or directory (os error 2)".to_string()),
+    ]);
+    assert_eq!(
+      error.to_string(),
+      "No terminal could be opened: kitty: kitty exited with exit status: 1; \
+       xterm: No such file or directory (os error 2)"
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn shutdown_agents_kills_agents_that_ignore_sigterm() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut child =
+      spawn_detached(Command::new("sh").args(["-c", "trap '' TERM; sleep 30; true"])).expect("spawn sh");
+    let registry = AgentRegistry::default();
+    let launched = LaunchedTerminal { pid: child.id(), terminal: "sh".to_string(), tty: None, child: None };
+    registry.register(&AgentSpec { command: "sleep 30".to_string(), ..AgentSpec::default() }, &launched);
+    // Give sh a moment to install the trap before it is signalled
+    thread::sleep(std::time::Duration::from_millis(200));
+
+    shutdown_agents(&registry);
+    assert_eq!(child.wait().expect("wait for sh").signal(), Some(9));
+  }
+
+  #[test]
+  fn diagnostics_cover_the_platform_config_and_agents() {
+    let config = TerminalConfig { max_retries: Some(3), ..TerminalConfig::default() };
+    let report = diagnostics_report(&config, &AgentRegistry::default());
+    assert_eq!(report.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(report.os, std::env::consts::OS);
+    assert_eq!(report.active_agents, 0);
+    // Whether a terminal is found depends on the machine, but one of the two says
+    assert_ne!(report.default_terminal.is_some(), report.default_terminal_error.is_some());
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["config"]["max_retries"], 3);
+    assert_eq!(json["config"]["linux"]["terminal"], serde_json::Value::Null);
+    assert!(json["terminals"].is_array());
+  }
+
+  #[test]
+  fn macos_terminal_script_is_well_formed() {
+    let commands = [
+      "claude",
+      r#"echo "hello world""#,
+      r"echo back\slash",
+      "ls; echo done",
+      r#"echo "\"; tell application \"Finder\" to quit""#,
+      r#"ends with backslash \"#,
+    ];
+    for command in commands {
+      let script = MacTerminal::Terminal.script(command, None, None, false, None);
+      assert_eq!(parse_do_script_literal(&script), command);
+    }
+  }
+}
@@ -0,0 +1,188 @@
+use std::{
+  collections::HashMap,
+  io::{Read, Write},
+  process::Command,
+  sync::Mutex,
+};
+
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+
+use crate::AgentError;
+
+/// Size pseudo-terminals start at, until the frontend resizes them.
+pub const DEFAULT_ROWS: u16 = 24;
+pub const DEFAULT_COLS: u16 = 80;
+
+/// The app's end of an agent's pseudo-terminal.
+pub struct PtySession {
+  master: Box<dyn MasterPty + Send>,
+  writer: Box<dyn Write + Send>,
+}
+
+/// An agent freshly started in a pseudo-terminal.
+pub struct SpawnedPty {
+  pub pid: u32,
+  pub child: Box<dyn Child + Send + Sync>,
+  /// Everything the agent writes to its terminal.
+  pub reader: Box<dyn Read + Send>,
+  pub session: PtySession,
+}
+
+/// Starts `command` in a new pseudo-terminal of `rows` by `cols`.
+///
+/// Only the program, arguments, environment and directory of `command` are
+/// used; its stdio is the terminal. `TERM` defaults to `xterm-256color`
+/// since the output is meant for a terminal emulator in the frontend.
+pub fn spawn(command: &Command, rows: u16, cols: u16) -> Result<SpawnedPty, AgentError> {
+  let pair = native_pty_system()
+    .openpty(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+    .map_err(|e| AgentError::PtyFailed(e.to_string()))?;
+
+  let mut builder = CommandBuilder::new(command.get_program());
+  builder.args(command.get_args());
+  if builder.get_env("TERM").is_none() {
+    builder.env("TERM", "xterm-256color");
+  }
+  for (name, value) in command.get_envs() {
+    match value {
+      Some(value) => builder.env(name, value),
+      None => builder.env_remove(name),
+    }
+  }
+  if let Some(dir) = command.get_current_dir() {
+    builder.cwd(dir);
+  }
+
+  let mut child = pair.slave.spawn_command(builder).map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+  // Without its pid the agent couldn't be tracked or stopped, and pid 0
+  // would stand for the app's own process group
+  let Some(pid) = child.process_id() else {
+    let _ = child.kill();
+    return Err(AgentError::PtyFailed("the agent's process id is unknown".to_string()));
+  };
+  // Our copy of the slave has to go so reads see the end once the agent exits
+  drop(pair.slave);
+
+  let reader = pair.master.try_clone_reader().map_err(|e| AgentError::PtyFailed(e.to_string()))?;
+  let writer = pair.master.take_writer().map_err(|e| AgentError::PtyFailed(e.to_string()))?;
+  Ok(SpawnedPty {
+    pid,
+    child,
+    reader,
+    session: PtySession { master: pair.master, writer },
+  })
+}
+
+/// Reads `reader` until the agent exits, handing each chunk to `on_output`
+/// and appending the raw bytes to `log` if there is one.
+///
+/// A chunk can end partway through a multi-byte character; those bytes are
+/// held back until the rest arrives, so every chunk is valid UTF-8.
+pub fn forward_output(
+  mut reader: impl Read,
+  mut log: Option<std::fs::File>,
+  mut on_output: impl FnMut(String),
+) {
+  let mut buffer = [0u8; 4096];
+  let mut pending = Vec::new();
+  // Linux reports the end of a pseudo-terminal as an error (EIO), not EOF
+  while let Ok(read @ 1..) = reader.read(&mut buffer) {
+    if let Some(log) = log.as_mut() {
+      let _ = log.write_all(&buffer[..read]);
+    }
+    pending.extend_from_slice(&buffer[..read]);
+    let text = take_utf8(&mut pending);
+    if !text.is_empty() {
+      on_output(text);
+    }
+  }
+  if !pending.is_empty() {
+    on_output(String::from_utf8_lossy(&pending).into_owned());
+  }
+}
+
+/// Removes and returns the longest prefix of `bytes` that is complete UTF-8,
+/// leaving an unfinished character at the end behind. Invalid bytes are
+/// replaced rather than held back forever.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+  let complete = match std::str::from_utf8(bytes) {
+    Ok(_) => bytes.len(),
+    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+    Err(_) => bytes.len(),
+  };
+  let rest = bytes.split_off(complete);
+  let text = String::from_utf8_lossy(bytes).into_owned();
+  *bytes = rest;
+  text
+}
+
+/// Pseudo-terminals of the agents launched with `pty`, by agent id, managed
+/// as Tauri state.
+#[derive(Default)]
+pub struct PtySessions {
+  sessions: Mutex<HashMap<String, PtySession>>,
+}
+
+impl PtySessions {
+  pub fn insert(&self, id: &str, session: PtySession) {
+    self.sessions.lock().unwrap().insert(id.to_string(), session);
+  }
+
+  /// Closes the app's end of an agent's terminal once it has exited.
+  pub fn remove(&self, id: &str) {
+    self.sessions.lock().unwrap().remove(id);
+  }
+
+  /// Types `data` into the agent's terminal.
+  pub fn write(&self, id: &str, data: &str) -> Result<(), AgentError> {
+    let mut sessions = self.sessions.lock().unwrap();
+    let session = sessions.get_mut(id).ok_or_else(|| AgentError::AgentNotFound(id.to_string()))?;
+    session
+      .writer
+      .write_all(data.as_bytes())
+      .and_then(|()| session.writer.flush())
+      .map_err(|e| AgentError::PtyFailed(e.to_string()))
+  }
+
+  pub fn resize(&self, id: &str, rows: u16, cols: u16) -> Result<(), AgentError> {
+    let sessions = self.sessions.lock().unwrap();
+    let session = sessions.get(id).ok_or_else(|| AgentError::AgentNotFound(id.to_string()))?;
+    session
+      .master
+      .resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+      .map_err(|e| AgentError::PtyFailed(e.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn take_utf8_holds_back_unfinished_characters() {
+    let mut bytes = "hé".as_bytes()[..2].to_vec();
+    assert_eq!(take_utf8(&mut bytes), "h");
+    assert_eq!(bytes, [0xc3]);
+    bytes.push(0xa9);
+    assert_eq!(take_utf8(&mut bytes), "é");
+    assert!(bytes.is_empty());
+
+    let mut invalid = vec![b'a', 0xff, b'b'];
+    assert_eq!(take_utf8(&mut invalid), "a\u{fffd}b");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn spawned_command_sees_a_terminal() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "test -t 1 && echo tty; stty size"]).current_dir("/");
+    let mut pty = spawn(&command, 30, 100).unwrap();
+
+    let mut output = String::new();
+    let reader = std::mem::replace(&mut pty.reader, Box::new(std::io::empty()));
+    let waiter = std::thread::spawn(move || pty.child.wait().map(|status| status.exit_code()));
+    forward_output(reader, None, |chunk| output.push_str(&chunk));
+    assert_eq!(waiter.join().unwrap().unwrap(), 0);
+    assert_eq!(output, "tty\r\n30 100\r\n");
+  }
+}
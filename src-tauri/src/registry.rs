@@ -0,0 +1,746 @@
+use std::{
+  collections::{HashMap, HashSet},
+  path::PathBuf,
+  sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Mutex,
+  },
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AgentError, shell_word, AgentSpec, LaunchedTerminal};
+
+/// What the frontend sees for each agent that is still running.
+///
+/// This is also what the launch commands return, serialized as
+///
+/// ```json
+/// { "id": "agent-1", "pid": 4242, "command": "claude", "terminal": "kitty",
+///   "title": null, "launched_at": 1700000000000, "working_directory": "/work",
+///   "log_path": null, "group": null }
+/// ```
+///
+/// `id` is what `restart_agent` takes; `pid` is what `kill_agent_terminal`
+/// and `focus_agent_terminal` take.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentInfo {
+  pub id: String,
+  pub pid: u32,
+  pub command: String,
+  pub terminal: String,
+  pub title: Option<String>,
+  /// Where the agent was started; unset means the terminal's own default.
+  pub working_directory: Option<String>,
+  /// Milliseconds since the Unix epoch.
+  pub launched_at: u64,
+  /// File the agent's output is being copied to, if logging was requested.
+  pub log_path: Option<String>,
+  /// The group it was launched in with `launch_agent_group`, if any.
+  #[serde(default)]
+  pub group: Option<String>,
+}
+
+/// A launched agent and what is needed to tell whether it is still alive.
+/// This is also what the registry file holds for each agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentHandle {
+  pub info: AgentInfo,
+  /// Controlling terminal of the agent's tab, when the process that was
+  /// spawned only hands the command off (osascript on macOS).
+  pub tty: Option<String>,
+  /// What was launched, with the config defaults filled in, for restarts.
+  pub spec: AgentSpec,
+  /// How the process we were watching exited, once it has and if that is
+  /// known.
+  #[serde(default)]
+  pub exit_code: Option<i32>,
+  /// Environment variables that look like credentials, whose values are
+  /// left out of the registry file. An agent loaded from it with any of
+  /// these can only be restarted once they are given again.
+  #[serde(default)]
+  pub redacted_env: Vec<String>,
+}
+
+/// Whether an agent is running, as returned by `agent_status`:
+///
+/// ```json
+/// { "status": "running", "pid": 4242, "uptime_ms": 60000 }
+/// { "status": "exited", "code": 0 }
+/// { "status": "unknown" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AgentStatus {
+  Running { pid: u32, uptime_ms: u64 },
+  /// `code` is only known for processes the app started and waited on.
+  Exited { code: Option<i32> },
+  /// No agent with that id was launched.
+  Unknown,
+}
+
+impl AgentHandle {
+  pub fn is_alive(&self) -> bool {
+    match &self.tty {
+      #[cfg(unix)]
+      Some(tty) => crate::tty_in_use(tty),
+      _ => crate::process_exists(self.info.pid),
+    }
+  }
+
+  /// Whether the process running as the agent's pid is the one that was
+  /// launched, rather than a newer one the pid was handed to after the agent
+  /// exited. Agents tracked by their tty are taken at their word.
+  fn is_original_process(&self) -> bool {
+    if self.tty.is_some() {
+      return true;
+    }
+    match crate::process_started_at(self.info.pid) {
+      Some(started) => started <= self.info.launched_at + PID_REUSE_SLACK_MS,
+      None => true,
+    }
+  }
+
+  /// The entry as it is written to the registry file, without the values of
+  /// any credentials in its environment.
+  fn for_saving(&self) -> AgentHandle {
+    let mut saved = self.clone();
+    if let Some(env) = &mut saved.spec.env {
+      env.retain(|name, _| {
+        let sensitive = crate::audit::is_sensitive(name);
+        if sensitive {
+          saved.redacted_env.push(name.clone());
+        }
+        !sensitive
+      });
+      saved.redacted_env.sort();
+    }
+    saved
+  }
+
+  /// Probes the agent's process to see whether it is still running.
+  pub fn status(&self) -> AgentStatus {
+    if self.is_alive() {
+      AgentStatus::Running { pid: self.info.pid, uptime_ms: now_ms().saturating_sub(self.info.launched_at) }
+    } else {
+      AgentStatus::Exited { code: self.exit_code }
+    }
+  }
+
+  /// Whether the agent's terminal may still be waiting out the startup delay,
+  /// so the command itself hasn't run yet. The delay only starts once the
+  /// terminal is up, so this errs towards "already running". Launches without
+  /// a terminal emulator (headless, pty, tmux) have no delay.
+  pub fn startup_pending(&self) -> bool {
+    let delayed = !matches!(self.info.terminal.as_str(), "headless" | "pty" | "tmux");
+    delayed && now_ms() < self.info.launched_at + self.spec.startup_delay_ms()
+  }
+}
+
+/// A group of agents launched together, as returned by `list_groups`:
+///
+/// ```json
+/// { "id": "review", "members": [{ "agent": { "id": "agent-1", ... },
+///   "status": { "status": "running", "pid": 4242, "uptime_ms": 60000 } }] }
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentGroup {
+  pub id: String,
+  /// Every agent launched in the group, running or not, oldest first.
+  pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupMember {
+  pub agent: AgentInfo,
+  pub status: AgentStatus,
+}
+
+/// How much later than its recorded launch an agent's process may appear to
+/// have started. Start times are only known to the second on Unix.
+const PID_REUSE_SLACK_MS: u64 = 2_000;
+
+/// Agents launched by the app, managed as Tauri state.
+#[derive(Default)]
+pub struct AgentRegistry {
+  agents: Mutex<HashMap<String, AgentHandle>>,
+  next_id: AtomicU64,
+  /// Launches that have reserved a slot but aren't registered yet.
+  launching: AtomicUsize,
+  /// Launches that are still in progress, by id, with the command line and
+  /// working directory of each.
+  pending: Mutex<HashMap<String, (String, Option<String>)>>,
+  /// File the agents are saved to after every change, so a restarted app
+  /// can pick up the ones still running.
+  path: Option<PathBuf>,
+}
+
+/// Room for one agent that is being launched, and the id it will have, from
+/// `AgentRegistry::reserve`. Dropping it without registering gives both back.
+pub struct LaunchSlot<'a> {
+  registry: &'a AgentRegistry,
+  id: String,
+}
+
+impl LaunchSlot<'_> {
+  /// The id the agent is registered under once it has launched.
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+
+  /// Registers the launched agent, which then takes the reservation's place.
+  pub fn register(self, spec: &AgentSpec, launched: &LaunchedTerminal) -> AgentInfo {
+    self.registry.insert(self.id.clone(), spec, launched)
+  }
+}
+
+impl Drop for LaunchSlot<'_> {
+  fn drop(&mut self) {
+    self.registry.pending.lock().unwrap().remove(&self.id);
+    self.registry.launching.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl AgentRegistry {
+  /// Loads the agents saved at `path` by an earlier run of the app, keeping
+  /// only those still running, and saves any later changes back there. A
+  /// missing or unreadable file starts the registry empty. Nothing watches
+  /// the loaded agents for exiting until the caller starts that.
+  pub fn load(path: PathBuf) -> Self {
+    let saved: Vec<AgentHandle> = match std::fs::read_to_string(&path) {
+      Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!("Ignoring invalid agent registry {}: {}", path.display(), e);
+        Vec::new()
+      }),
+      Err(_) => Vec::new(),
+    };
+    let agents: HashMap<String, AgentHandle> = saved
+      .into_iter()
+      .filter(|handle| handle.is_alive() && handle.is_original_process())
+      .map(|handle| (handle.info.id.clone(), handle))
+      .collect();
+    // Carry on numbering after the agents that survived so ids stay unique
+    let last_id = agents
+      .keys()
+      .filter_map(|id| id.strip_prefix("agent-")?.parse().ok())
+      .max()
+      .unwrap_or(0);
+
+    let registry = AgentRegistry {
+      agents: Mutex::new(HashMap::new()),
+      next_id: AtomicU64::new(last_id),
+      launching: AtomicUsize::new(0),
+      pending: Mutex::new(HashMap::new()),
+      path: Some(path),
+    };
+    registry.save(&agents);
+    *registry.agents.lock().unwrap() = agents;
+    registry
+  }
+
+  /// Writes `agents` to the registry file, if there is one. Called with the
+  /// lock held so saves happen in the same order as the changes.
+  fn save(&self, agents: &HashMap<String, AgentHandle>) {
+    let Some(path) = &self.path else {
+      return;
+    };
+    let mut handles: Vec<AgentHandle> = agents.values().map(AgentHandle::for_saving).collect();
+    handles.sort_by_key(|handle| handle.info.launched_at);
+    // Written next to the file and renamed over it, so a crash mid-write
+    // can't leave half a registry behind
+    let partial = path.with_extension("json.partial");
+    let saved = serde_json::to_vec_pretty(&handles)
+      .map_err(std::io::Error::from)
+      .and_then(|json| {
+        path.parent().map_or(Ok(()), std::fs::create_dir_all)?;
+        std::fs::write(&partial, json)
+      })
+      .and_then(|()| std::fs::rename(&partial, path));
+    if let Err(e) = saved {
+      log::error!("Could not save agent registry {}: {}", path.display(), e);
+    }
+  }
+
+  /// Reserves room for launching `spec`, or fails with `AgentLimitReached` if
+  /// its `max_concurrent_agents` are already running or being launched.
+  /// Agents that have exited don't count.
+  ///
+  /// The slot holds the agent's id from the start: `spec.id` if the caller
+  /// chose one, which fails with `DuplicateAgentId` while another agent with
+  /// it is running or launching, or else the next `agent-N`. An exited
+  /// agent's id can be taken over.
+  ///
+  /// Fails with `DuplicateLaunch` while the same command is being launched in
+  /// the same directory, or was less than `spec.dedupe_window_ms()` ago.
+  pub fn reserve(&self, spec: &AgentSpec) -> Result<LaunchSlot<'_>, AgentError> {
+    let launch = (spec.command_line(shell_word).trim().to_string(), spec.working_directory.clone());
+    let window = spec.dedupe_window_ms();
+    let recent = |handle: &AgentHandle| {
+      handle.info.command == launch.0
+        && handle.info.working_directory == launch.1
+        && now_ms() < handle.info.launched_at + window
+    };
+    let exited = self.exited(|handle| {
+      spec.max_concurrent_agents.is_some() || spec.id.as_deref() == Some(&handle.info.id) || recent(handle)
+    });
+    // Agents registered since `exited` looked have only just started
+    let is_alive = |handle: &AgentHandle| !exited.contains(&(handle.info.id.clone(), handle.info.pid));
+
+    // Counting and reserving under the lock keeps parallel launches from
+    // overshooting the limit or sharing an id together
+    let agents = self.agents.lock().unwrap();
+    if let Some(limit) = spec.max_concurrent_agents {
+      let running = agents.values().filter(|handle| is_alive(handle)).count();
+      if running + self.launching.load(Ordering::SeqCst) >= limit {
+        return Err(AgentError::AgentLimitReached(limit));
+      }
+    }
+    let mut pending = self.pending.lock().unwrap();
+    let id = match spec.id.as_deref() {
+      Some(id) => {
+        if id.trim().is_empty() || id.trim() != id {
+          return Err(AgentError::InvalidAgentId(id.to_string()));
+        }
+        if pending.contains_key(id) || agents.get(id).is_some_and(is_alive) {
+          return Err(AgentError::DuplicateAgentId(id.to_string()));
+        }
+        id.to_string()
+      }
+      None => self.generate_id(&agents, &pending),
+    };
+    if window > 0 {
+      let launching = pending.iter().find(|(_, pending)| **pending == launch).map(|(id, _)| id);
+      let launched =
+        agents.values().find(|handle| recent(handle) && is_alive(handle)).map(|handle| &handle.info.id);
+      if let Some(existing) = launching.or(launched) {
+        return Err(AgentError::DuplicateLaunch(existing.clone()));
+      }
+    }
+    pending.insert(id.clone(), launch);
+    self.launching.fetch_add(1, Ordering::SeqCst);
+    Ok(LaunchSlot { registry: self, id })
+  }
+
+  /// Returns the id and pid of each agent picked by `filter` that has exited.
+  /// Probing an agent can mean running `ps`, so it's done on a copy of them
+  /// rather than while holding the lock.
+  fn exited(&self, filter: impl Fn(&AgentHandle) -> bool) -> HashSet<(String, u32)> {
+    let agents = self.agents.lock().unwrap();
+    let picked: Vec<AgentHandle> = agents.values().filter(|handle| filter(handle)).cloned().collect();
+    drop(agents);
+    picked
+      .into_iter()
+      .filter(|handle| !handle.is_alive())
+      .map(|handle| (handle.info.id, handle.info.pid))
+      .collect()
+  }
+
+  /// Returns the next `agent-N` that no agent, launched or launching, has.
+  /// Callers can pick ids of that form themselves, so those are skipped.
+  fn generate_id<T>(&self, agents: &HashMap<String, AgentHandle>, pending: &HashMap<String, T>) -> String {
+    loop {
+      let id = format!("agent-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+      if !agents.contains_key(&id) && !pending.contains_key(&id) {
+        return id;
+      }
+    }
+  }
+
+  /// Records a terminal freshly launched for `spec` without reserving a slot
+  /// first, under `spec.id` if it has one. Launches go through `reserve`.
+  #[cfg(test)]
+  pub fn register(&self, spec: &AgentSpec, launched: &LaunchedTerminal) -> AgentInfo {
+    let id = match &spec.id {
+      Some(id) => id.clone(),
+      None => self.generate_id(&self.agents.lock().unwrap(), &self.pending.lock().unwrap()),
+    };
+    self.insert(id, spec, launched)
+  }
+
+  /// Records a terminal freshly launched for `spec` under `id` and returns
+  /// its registry entry.
+  fn insert(&self, id: String, spec: &AgentSpec, launched: &LaunchedTerminal) -> AgentInfo {
+    let mut agents = self.agents.lock().unwrap();
+    let info = AgentInfo {
+      id: id.clone(),
+      pid: launched.pid,
+      command: spec.command_line(shell_word).trim().to_string(),
+      terminal: launched.terminal.clone(),
+      title: spec.title.clone(),
+      working_directory: spec.working_directory.clone(),
+      launched_at: now_ms(),
+      log_path: spec.log_path.clone(),
+      group: spec.group.clone(),
+    };
+    let handle = AgentHandle {
+      info: info.clone(),
+      tty: launched.tty.clone(),
+      spec: spec.clone(),
+      exit_code: None,
+      redacted_env: Vec::new(),
+    };
+    agents.insert(id, handle);
+    self.save(&agents);
+    info
+  }
+
+  /// Looks up an agent by its registry id.
+  pub fn get(&self, id: &str) -> Option<AgentHandle> {
+    self.agents.lock().unwrap().get(id).cloned()
+  }
+
+  /// Notes how an agent exited, for `agent_status`.
+  pub fn record_exit(&self, id: &str, exit_code: Option<i32>) {
+    let mut agents = self.agents.lock().unwrap();
+    if let Some(handle) = agents.get_mut(id) {
+      handle.exit_code = exit_code;
+      self.save(&agents);
+    }
+  }
+
+  /// Puts back an entry taken out with `remove`.
+  pub fn restore(&self, handle: AgentHandle) {
+    let mut agents = self.agents.lock().unwrap();
+    agents.insert(handle.info.id.clone(), handle);
+    self.save(&agents);
+  }
+
+  /// Forgets an agent, returning its entry if it was registered.
+  pub fn remove(&self, id: &str) -> Option<AgentHandle> {
+    let mut agents = self.agents.lock().unwrap();
+    let removed = agents.remove(id);
+    if removed.is_some() {
+      self.save(&agents);
+    }
+    removed
+  }
+
+  /// Every registered agent, running or not.
+  pub fn handles(&self) -> Vec<AgentHandle> {
+    self.agents.lock().unwrap().values().cloned().collect()
+  }
+
+  /// Looks up the agent that was started as `pid`.
+  pub fn find_by_pid(&self, pid: u32) -> Option<AgentHandle> {
+    let agents = self.agents.lock().unwrap();
+    agents.values().find(|handle| handle.info.pid == pid).cloned()
+  }
+
+  /// The agents launched in `group`, running or not, oldest first.
+  pub fn group_members(&self, group: &str) -> Vec<AgentHandle> {
+    let agents = self.agents.lock().unwrap();
+    let mut members: Vec<AgentHandle> =
+      agents.values().filter(|handle| handle.info.group.as_deref() == Some(group)).cloned().collect();
+    // Agents launched in the same millisecond stay in a stable order
+    members.sort_by_key(|handle| (handle.info.launched_at, handle.info.id.clone()));
+    members
+  }
+
+  /// Every group any registered agent belongs to, by id, with the status of
+  /// each member. Members are probed on every call.
+  pub fn groups(&self) -> Vec<AgentGroup> {
+    let agents = self.agents.lock().unwrap();
+    let mut ids: Vec<String> = agents.values().filter_map(|handle| handle.info.group.clone()).collect();
+    drop(agents);
+    ids.sort();
+    ids.dedup();
+    ids
+      .into_iter()
+      .map(|id| {
+        let members = self
+          .group_members(&id)
+          .into_iter()
+          .map(|handle| GroupMember { status: handle.status(), agent: handle.info })
+          .collect();
+        AgentGroup { id, members }
+      })
+      .collect()
+  }
+
+  /// Returns the agents that are still running. Exited agents stay registered
+  /// so they can still be restarted.
+  pub fn list_active(&self) -> Vec<AgentInfo> {
+    let agents: Vec<AgentHandle> = self.agents.lock().unwrap().values().cloned().collect();
+    // Probed after the lock is released, since that can mean running `ps`
+    let mut active: Vec<AgentInfo> =
+      agents.into_iter().filter(|handle| handle.is_alive()).map(|handle| handle.info).collect();
+    active.sort_by_key(|info| info.launched_at);
+    active
+  }
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn launched(pid: u32) -> LaunchedTerminal {
+    LaunchedTerminal { pid, terminal: "xterm".to_string(), tty: None, child: None }
+  }
+
+  fn spec(command: &str) -> AgentSpec {
+    AgentSpec { command: command.to_string(), ..AgentSpec::default() }
+  }
+
+  /// A launch of `claude` that isn't held back by the ones before it.
+  #[cfg(unix)]
+  fn launch(limit: Option<usize>, id: Option<&str>) -> AgentSpec {
+    let id = id.map(str::to_string);
+    AgentSpec { id, max_concurrent_agents: limit, dedupe_window_ms: Some(0), ..spec("claude") }
+  }
+
+  #[test]
+  fn register_assigns_unique_ids() {
+    let registry = AgentRegistry::default();
+    let first = registry.register(&spec("claude"), &launched(std::process::id()));
+    let second = registry.register(&spec("claude"), &launched(std::process::id()));
+    assert_ne!(first.id, second.id);
+    assert_eq!(first.command, "claude");
+    assert_eq!(first.terminal, "xterm");
+    assert_eq!(first.working_directory, None);
+  }
+
+  #[test]
+  fn agent_info_serializes_documented_shape() {
+    let registry = AgentRegistry::default();
+    let spec = AgentSpec { working_directory: Some("/work".to_string()), ..spec("claude") };
+    let info = serde_json::to_value(registry.register(&spec, &launched(4242))).unwrap();
+    let mut keys: Vec<&str> = info.as_object().unwrap().keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(
+      keys,
+      ["command", "group", "id", "launched_at", "log_path", "pid", "terminal", "title", "working_directory"]
+    );
+    assert_eq!(info["working_directory"], "/work");
+  }
+
+  #[test]
+  fn find_by_pid_returns_registered_agent() {
+    let registry = AgentRegistry::default();
+    let agent = registry.register(&spec("claude"), &launched(4242));
+    assert_eq!(registry.find_by_pid(4242).unwrap().info.id, agent.id);
+    assert!(registry.find_by_pid(4243).is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn list_active_skips_dead_processes() {
+    let registry = AgentRegistry::default();
+    let alive = registry.register(&spec("claude"), &launched(std::process::id()));
+    let gone = registry.register(&spec("gone"), &launched(i32::MAX as u32));
+
+    let active = registry.list_active();
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].id, alive.id);
+    assert_eq!(registry.get(&gone.id).unwrap().spec.command, "gone");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reserve_counts_running_and_pending_agents() {
+    let registry = AgentRegistry::default();
+    registry.register(&spec("claude"), &launched(std::process::id()));
+    registry.register(&spec("gone"), &launched(i32::MAX as u32));
+
+    let slot = registry.reserve(&launch(Some(2), None)).unwrap();
+    assert_eq!(registry.reserve(&launch(Some(2), None)).err(), Some(AgentError::AgentLimitReached(2)));
+    assert!(registry.reserve(&launch(None, None)).is_ok());
+    drop(slot);
+    let slot = registry.reserve(&launch(Some(2), None)).unwrap();
+    slot.register(&spec("claude"), &launched(std::process::id()));
+    assert!(registry.reserve(&launch(Some(2), None)).is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reserve_rejects_ids_in_use() {
+    let registry = AgentRegistry::default();
+    let named = AgentSpec { id: Some("planner".to_string()), ..spec("claude") };
+    let slot = registry.reserve(&launch(None, Some("planner"))).unwrap();
+    assert_eq!(slot.id(), "planner");
+    assert_eq!(
+      registry.reserve(&launch(None, Some("planner"))).err(),
+      Some(AgentError::DuplicateAgentId("planner".to_string()))
+    );
+    let info = slot.register(&named, &launched(std::process::id()));
+    assert_eq!(info.id, "planner");
+    assert!(registry.reserve(&launch(None, Some("planner"))).is_err());
+    let blank = registry.reserve(&launch(None, Some(" ")));
+    assert_eq!(blank.err(), Some(AgentError::InvalidAgentId(" ".to_string())));
+
+    // Once it has exited the id is free again
+    let gone = AgentSpec { id: Some("gone".to_string()), ..spec("gone") };
+    registry.register(&gone, &launched(i32::MAX as u32));
+    assert!(registry.reserve(&launch(None, Some("gone"))).is_ok());
+
+    // Generated ids step over ones callers picked, and are known before the launch
+    registry.register(&AgentSpec { id: Some("agent-1".to_string()), ..spec("claude") }, &launched(4242));
+    let slot = registry.reserve(&launch(None, None)).unwrap();
+    assert_eq!(slot.id(), "agent-2");
+    assert_eq!(registry.reserve(&launch(None, None)).unwrap().id(), "agent-3");
+    assert_eq!(slot.register(&spec("claude"), &launched(4242)).id, "agent-2");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reserve_refuses_the_same_launch_twice_in_a_row() {
+    let registry = AgentRegistry::default();
+    let here = AgentSpec { working_directory: Some("/work".to_string()), ..spec("claude") };
+    let slot = registry.reserve(&here).unwrap();
+    let id = slot.id().to_string();
+    assert_eq!(registry.reserve(&here).err(), Some(AgentError::DuplicateLaunch(id.clone())));
+    slot.register(&here, &launched(std::process::id()));
+    assert_eq!(registry.reserve(&here).err(), Some(AgentError::DuplicateLaunch(id)));
+
+    // Another command or directory, turning it off, or the window passing, all let it through
+    assert!(registry.reserve(&spec("claude")).is_ok());
+    assert!(registry.reserve(&AgentSpec { command: "aider".to_string(), ..here.clone() }).is_ok());
+    assert!(registry.reserve(&AgentSpec { dedupe_window_ms: Some(0), ..here.clone() }).is_ok());
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert!(registry.reserve(&AgentSpec { dedupe_window_ms: Some(10), ..here }).is_ok());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn groups_list_their_members_and_statuses() {
+    let registry = AgentRegistry::default();
+    let in_group = |group: &str, command: &str| AgentSpec { group: Some(group.to_string()), ..spec(command) };
+    let planner = registry.register(&in_group("review", "planner"), &launched(std::process::id()));
+    let worker = registry.register(&in_group("review", "worker"), &launched(i32::MAX as u32));
+    registry.register(&in_group("docs", "writer"), &launched(std::process::id()));
+    registry.register(&spec("loner"), &launched(std::process::id()));
+
+    let groups = registry.groups();
+    assert_eq!(groups.iter().map(|group| group.id.as_str()).collect::<Vec<_>>(), ["docs", "review"]);
+    let review = &groups[1];
+    let ids: Vec<&str> = review.members.iter().map(|member| member.agent.id.as_str()).collect();
+    assert_eq!(ids, [planner.id.as_str(), worker.id.as_str()]);
+    assert!(matches!(review.members[0].status, AgentStatus::Running { .. }));
+    assert_eq!(review.members[1].status, AgentStatus::Exited { code: None });
+    assert_eq!(registry.group_members("review").len(), 2);
+    assert!(registry.group_members("nobody").is_empty());
+  }
+
+  #[test]
+  fn startup_pending_only_during_the_delay() {
+    let registry = AgentRegistry::default();
+    let waiting = AgentSpec { startup_delay_ms: Some(60_000), ..spec("claude") };
+    let agent = registry.register(&waiting, &launched(4242));
+    assert!(registry.get(&agent.id).unwrap().startup_pending());
+
+    let immediate = AgentSpec { startup_delay_ms: Some(0), ..spec("claude") };
+    let agent = registry.register(&immediate, &launched(4242));
+    assert!(!registry.get(&agent.id).unwrap().startup_pending());
+
+    let headless = LaunchedTerminal { terminal: "headless".to_string(), ..launched(4242) };
+    let agent = registry.register(&waiting, &headless);
+    assert!(!registry.get(&agent.id).unwrap().startup_pending());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn load_keeps_running_agents_and_continues_ids() {
+    let dir = std::env::temp_dir().join(format!("agentic-sync-registry-{}", std::process::id()));
+    let path = dir.join("agents.json");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let registry = AgentRegistry::load(path.clone());
+    let alive = registry.register(&spec("claude"), &launched(std::process::id()));
+    registry.register(&spec("gone"), &launched(i32::MAX as u32));
+    let removed = registry.register(&spec("removed"), &launched(std::process::id()));
+    registry.remove(&removed.id);
+
+    let reloaded = AgentRegistry::load(path.clone());
+    let handles = reloaded.handles();
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].info.id, alive.id);
+    assert_eq!(handles[0].spec.command, "claude");
+    let next = reloaded.register(&spec("claude"), &launched(std::process::id()));
+    assert_eq!(next.id, "agent-2");
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn credentials_in_the_env_are_not_saved() {
+    let dir = std::env::temp_dir().join(format!("agentic-sync-registry-env-{}", std::process::id()));
+    let path = dir.join("agents.json");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let registry = AgentRegistry::load(path.clone());
+    let env = HashMap::from([
+      ("GITHUB_TOKEN".to_string(), "ghp_secret".to_string()),
+      ("MODEL".to_string(), "big".to_string()),
+    ]);
+    let with_env = AgentSpec { env: Some(env), ..spec("claude") };
+    let agent = registry.register(&with_env, &launched(std::process::id()));
+    // The running app keeps the value it launched with
+    assert_eq!(registry.get(&agent.id).unwrap().spec.env.unwrap()["GITHUB_TOKEN"], "ghp_secret");
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("ghp_secret"));
+    let reloaded = AgentRegistry::load(path.clone()).get(&agent.id).unwrap();
+    assert_eq!(reloaded.redacted_env, ["GITHUB_TOKEN"]);
+    assert_eq!(reloaded.spec.env.unwrap(), HashMap::from([("MODEL".to_string(), "big".to_string())]));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn load_drops_agents_whose_pid_was_reused() {
+    let dir = std::env::temp_dir().join(format!("agentic-sync-registry-reused-{}", std::process::id()));
+    let path = dir.join("agents.json");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let registry = AgentRegistry::default();
+    let original = registry.register(&spec("claude"), &launched(std::process::id()));
+    let reused = registry.register(&spec("claude"), &launched(std::process::id()));
+    let mut handles = registry.handles();
+    for handle in &mut handles {
+      if handle.info.id == reused.id {
+        // This test process started long after an agent launched in 2001
+        handle.info.launched_at = 1_000_000_000_000;
+      }
+    }
+    std::fs::write(&path, serde_json::to_string(&handles).unwrap()).unwrap();
+
+    let loaded = AgentRegistry::load(path).handles();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].info.id, original.id);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn status_probes_the_process() {
+    let registry = AgentRegistry::default();
+    let alive = registry.register(&spec("claude"), &launched(std::process::id()));
+    let status = registry.get(&alive.id).unwrap().status();
+    assert!(matches!(status, AgentStatus::Running { pid, .. } if pid == std::process::id()));
+
+    let gone = registry.register(&spec("gone"), &launched(i32::MAX as u32));
+    registry.record_exit(&gone.id, Some(3));
+    assert_eq!(registry.get(&gone.id).unwrap().status(), AgentStatus::Exited { code: Some(3) });
+    assert_eq!(
+      serde_json::to_value(AgentStatus::Exited { code: Some(3) }).unwrap(),
+      serde_json::json!({ "status": "exited", "code": 3 })
+    );
+  }
+
+  #[test]
+  fn remove_forgets_agent() {
+    let registry = AgentRegistry::default();
+    let agent = registry.register(&spec("claude"), &launched(4242));
+    assert!(registry.remove(&agent.id).is_some());
+    assert!(registry.get(&agent.id).is_none());
+    assert!(registry.remove(&agent.id).is_none());
+  }
+}